@@ -0,0 +1,20 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use halo2_mpt_circuits::{hash_traces, serde::SMTTrace, types::Proof, MPTProofType};
+
+fn bench(criterion: &mut Criterion) {
+    let json = include_str!("traces.json");
+    let witness: Vec<(MPTProofType, SMTTrace)> = serde_json::from_str(json).unwrap();
+    let proofs: Vec<_> = witness.into_iter().map(Proof::from).collect();
+
+    criterion.bench_function("hash traces", |bencher| {
+        bencher.iter(|| hash_traces(&proofs))
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench
+}
+
+criterion_main!(benches);
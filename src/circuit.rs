@@ -1,6 +1,9 @@
 use crate::{
-    gadgets::poseidon::PoseidonTable, hash_traces, serde::SMTTrace, types::Proof, MPTProofType,
-    MptCircuitConfig,
+    gadgets::poseidon::{check_hash_traces, PoseidonLoadError, PoseidonTable, MAX_POSEIDON_ROWS},
+    hash_traces,
+    serde::SMTTrace,
+    types::Proof,
+    MPTProofType, MptCircuitConfig,
 };
 use halo2_proofs::{
     circuit::{Layouter, SimpleFloorPlanner},
@@ -16,10 +19,23 @@ pub struct TestCircuit {
 
 impl TestCircuit {
     pub fn new(n_rows: usize, traces: Vec<(MPTProofType, SMTTrace)>) -> Self {
-        Self {
-            n_rows,
-            proofs: traces.into_iter().map(Proof::from).collect(),
-        }
+        Self::from_proofs(n_rows, traces.into_iter().map(Proof::from).collect())
+    }
+
+    pub fn from_proofs(n_rows: usize, proofs: Vec<Proof>) -> Self {
+        Self { n_rows, proofs }
+    }
+
+    /// Like `from_proofs`, but returns the error that loading the poseidon table would
+    /// otherwise only report as `Error::Synthesis`.
+    pub fn try_from_proofs(n_rows: usize, proofs: Vec<Proof>) -> Result<Self, PoseidonLoadError> {
+        let circuit = Self::from_proofs(n_rows, proofs);
+        check_hash_traces(&hash_traces(&circuit.proofs), circuit.poseidon_rows())?;
+        Ok(circuit)
+    }
+
+    fn poseidon_rows(&self) -> usize {
+        self.n_rows.max(MAX_POSEIDON_ROWS)
     }
 }
 
@@ -48,8 +64,16 @@ impl Circuit<Fr> for TestCircuit {
         layouter.assign_region(
             || "load poseidon table",
             |mut region| {
-                poseidon.load(&mut region, &hash_traces(&self.proofs));
-                Ok(())
+                poseidon
+                    .load(
+                        &mut region,
+                        &hash_traces(&self.proofs),
+                        self.poseidon_rows(),
+                    )
+                    .map_err(|e| {
+                        log::error!("{e}");
+                        Error::Synthesis
+                    })
             },
         )
     }
@@ -9,6 +9,8 @@ mod binary_column;
 mod binary_query;
 mod column;
 mod query;
+#[cfg(test)]
+pub(crate) mod test_circuit;
 
 pub use binary_column::BinaryColumn;
 pub use binary_query::BinaryQuery;
@@ -21,6 +23,7 @@ pub struct ConstraintBuilder<F: FromUniformBytes<64> + Ord> {
     lookups: Vec<(&'static str, Vec<(Query<F>, Query<F>)>)>,
 
     conditions: Vec<BinaryQuery<F>>,
+    every_row: SelectorColumn,
 }
 
 impl<F: FromUniformBytes<64> + Ord> ConstraintBuilder<F> {
@@ -30,6 +33,7 @@ impl<F: FromUniformBytes<64> + Ord> ConstraintBuilder<F> {
             lookups: vec![],
 
             conditions: vec![every_row.current()],
+            every_row,
         }
     }
 
@@ -40,6 +44,11 @@ impl<F: FromUniformBytes<64> + Ord> ConstraintBuilder<F> {
             .clone()
     }
 
+    /// True on the first row on which the every row selector is enabled.
+    pub fn first_enabled_row(&self) -> BinaryQuery<F> {
+        self.every_row.current().and(!self.every_row.rotation(-1))
+    }
+
     pub fn assert_zero(&mut self, name: &'static str, query: Query<F>) {
         let condition = self
             .conditions
@@ -56,6 +65,10 @@ impl<F: FromUniformBytes<64> + Ord> ConstraintBuilder<F> {
         self.assert_zero(name, Query::one() - condition);
     }
 
+    pub fn assert_boolean(&mut self, name: &'static str, query: Query<F>) {
+        self.assert_zero(name, query.clone() * (Query::one() - query));
+    }
+
     pub fn assert_unreachable(&mut self, name: &'static str) {
         self.assert(name, BinaryQuery::zero());
     }
@@ -66,6 +79,34 @@ impl<F: FromUniformBytes<64> + Ord> ConstraintBuilder<F> {
         self.conditions.pop().unwrap();
     }
 
+    /// Constrains `column` to be equal to its value in the previous row whenever `active` holds.
+    pub fn add_constant_within(
+        &mut self,
+        name: &'static str,
+        active: BinaryQuery<F>,
+        column: AdviceColumn,
+    ) {
+        self.condition(active, |cb| {
+            cb.assert_equal(name, column.current(), column.previous())
+        });
+    }
+
+    /// Constrains `value` to be one of `allowed` via the product of their differences. The degree
+    /// of the constraint grows with the number of allowed values, so this is only for small sets.
+    pub fn add_membership<T: Into<Query<F>>>(
+        &mut self,
+        name: &'static str,
+        value: Query<F>,
+        allowed: impl IntoIterator<Item = T>,
+    ) {
+        self.assert_zero(
+            name,
+            allowed
+                .into_iter()
+                .fold(Query::one(), |product, x| product * (value.clone() - x)),
+        );
+    }
+
     pub fn add_lookup<const N: usize>(
         &mut self,
         name: &'static str,
@@ -86,6 +127,40 @@ impl<F: FromUniformBytes<64> + Ord> ConstraintBuilder<F> {
         self.lookups.push((name, lookup))
     }
 
+    /// Adds a lookup of `left` into `right`, where `default` is looked up instead when the
+    /// current conditions do not hold. Unlike `add_lookup`, this does not require the table to
+    /// have an all 0 row, but `default` must be in it.
+    pub fn add_lookup_with_default<const N: usize>(
+        &mut self,
+        name: &'static str,
+        left: [Query<F>; N],
+        right: [Query<F>; N],
+        default: [Query<F>; N],
+    ) {
+        let condition = self
+            .conditions
+            .iter()
+            .fold(BinaryQuery::one(), |a, b| a.and(b.clone()));
+        let lookup = left
+            .into_iter()
+            .zip(default)
+            .map(|(left, default)| condition.select(left, default))
+            .zip(right)
+            .collect();
+        self.lookups.push((name, lookup))
+    }
+
+    /// Adds a lookup which only applies on rows where `enable` holds.
+    pub fn add_lookup_gated<const N: usize>(
+        &mut self,
+        name: &'static str,
+        enable: BinaryQuery<F>,
+        left: [Query<F>; N],
+        right: [Query<F>; N],
+    ) {
+        self.condition(enable, |cb| cb.add_lookup(name, left, right));
+    }
+
     pub fn poseidon_lookup(
         &mut self,
         name: &'static str,
@@ -160,6 +235,38 @@ impl<F: FromUniformBytes<64> + Ord> ConstraintBuilder<F> {
         [0; N].map(|_| BinaryColumn::configure::<F>(cs, self))
     }
 
+    /// The number of advice columns, fixed columns, gates, and lookups in `cs`. Comparing the
+    /// counts before and after configuring a circuit gives the width it adds.
+    pub fn column_counts(cs: &ConstraintSystem<F>) -> (usize, usize, usize, usize) {
+        (
+            cs.num_advice_columns(),
+            cs.num_fixed_columns(),
+            cs.gates().len(),
+            cs.lookups().len(),
+        )
+    }
+
+    /// The highest degree of the constraints added so far, including their conditions.
+    pub fn max_degree(&self) -> usize {
+        self.constraints
+            .iter()
+            .map(|(_, query)| query.degree())
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Same as `build`, but panics if any constraint has degree greater than `max`.
+    pub fn build_with_degree_assert(self, cs: &mut ConstraintSystem<F>, max: usize) {
+        for (name, query) in &self.constraints {
+            let degree = query.degree();
+            assert!(
+                degree <= max,
+                "constraint \"{name}\" has degree {degree}, which is more than {max}"
+            );
+        }
+        self.build(cs)
+    }
+
     pub fn build(self, cs: &mut ConstraintSystem<F>) {
         assert_eq!(
             self.conditions.len(),
@@ -180,3 +287,417 @@ impl<F: FromUniformBytes<64> + Ord> ConstraintBuilder<F> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{test_circuit::*, *};
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        dev::MockProver,
+        halo2curves::bn256::{Bn256, Fr},
+        plonk::{keygen_vk, Any, Circuit, Column, Error},
+        poly::kzg::commitment::ParamsKZG,
+    };
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+    struct ConstantWithin;
+
+    impl Configure for ConstantWithin {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let ([active], [], [column]) = cb.build_columns(cs);
+            cb.add_constant_within("column is constant while active", active.current(), column);
+            vec![active.0.into(), column.0.into()]
+        }
+    }
+
+    struct FirstEnabledRow;
+
+    impl Configure for FirstEnabledRow {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [column] = cb.advice_columns(cs);
+            cb.condition(cb.first_enabled_row(), |cb| {
+                cb.assert_equal(
+                    "column is 1 on first enabled row",
+                    column.current(),
+                    Query::one(),
+                )
+            });
+            vec![column.0.into()]
+        }
+    }
+
+    struct Membership;
+
+    impl Configure for Membership {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [column] = cb.advice_columns(cs);
+            cb.add_membership("column is 1, 3, or 5", column.current(), [1u64, 3, 5]);
+            vec![column.0.into()]
+        }
+    }
+
+    struct BinaryCombinators;
+
+    impl Configure for BinaryCombinators {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [a, b] = cb.binary_columns(cs);
+            let [and, or, xor, not, select] = cb.advice_columns(cs);
+            cb.assert_equal("and", and.current(), a.current().and(b.current()).into());
+            cb.assert_equal("or", or.current(), a.current().or(b.current()).into());
+            cb.assert_equal("xor", xor.current(), a.current().xor(b.current()).into());
+            cb.assert_equal("not", not.current(), (!a.current()).into());
+            cb.assert_equal(
+                "select",
+                select.current(),
+                a.current().select(Query::from(2), Query::from(3)),
+            );
+            [a.0, b.0, and.0, or.0, xor.0, not.0, select.0]
+                .map(Column::<Any>::from)
+                .to_vec()
+        }
+    }
+
+    #[test]
+    fn test_binary_combinators() {
+        // selector, a, b, and, or, xor, not, select
+        let mut rows = vec![
+            vec![1, 0, 0, 0, 0, 0, 1, 3],
+            vec![1, 0, 1, 0, 1, 1, 1, 3],
+            vec![1, 1, 0, 0, 1, 1, 0, 2],
+            vec![1, 1, 1, 1, 1, 0, 0, 2],
+        ];
+        assert_eq!(
+            TestCircuit::<BinaryCombinators>::verify(rows.clone()),
+            Ok(())
+        );
+
+        // 1 xor 1 = 1 is wrong.
+        rows[3][5] = 1;
+        assert!(TestCircuit::<BinaryCombinators>::verify(rows).is_err());
+    }
+
+    struct RlcSelect;
+
+    impl Configure for RlcSelect {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [condition] = cb.binary_columns(cs);
+            let [a, b, c, rlc, select] = cb.advice_columns(cs);
+            cb.assert_equal(
+                "rlc = a * 7^2 + b * 7 + c",
+                rlc.current(),
+                Query::rlc(&[a.current(), b.current(), c.current()], Query::from(7)),
+            );
+            cb.assert_equal(
+                "select = condition ? a : b",
+                select.current(),
+                Query::select(condition.current(), a.current(), b.current()),
+            );
+            [condition.0, a.0, b.0, c.0, rlc.0, select.0]
+                .map(Column::<Any>::from)
+                .to_vec()
+        }
+    }
+
+    #[test]
+    fn test_rlc_and_select() {
+        // selector, condition, a, b, c, rlc, select
+        let mut rows = vec![vec![1, 1, 1, 2, 3, 66, 1], vec![1, 0, 4, 5, 6, 237, 5]];
+        assert_eq!(TestCircuit::<RlcSelect>::verify(rows.clone()), Ok(()));
+
+        rows[1][5] += 1;
+        assert!(TestCircuit::<RlcSelect>::verify(rows).is_err());
+    }
+
+    #[test]
+    fn test_max_degree() {
+        let mut cs = ConstraintSystem::<Fr>::default();
+        let mut cb = ConstraintBuilder::new(SelectorColumn(cs.fixed_column()));
+        let [a, b] = cb.advice_columns(&mut cs);
+        // The every row selector adds 1 to the degree of a * b.
+        cb.assert_zero("degree 3", a.current() * b.current());
+        cb.assert_equal("degree 2", a.current(), b.current());
+        assert_eq!(cb.max_degree(), 3);
+        cb.build_with_degree_assert(&mut cs, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "constraint \"degree 3\" has degree 3")]
+    fn test_build_with_degree_assert() {
+        let mut cs = ConstraintSystem::<Fr>::default();
+        let mut cb = ConstraintBuilder::new(SelectorColumn(cs.fixed_column()));
+        let [a, b] = cb.advice_columns(&mut cs);
+        cb.assert_zero("degree 3", a.current() * b.current());
+        cb.build_with_degree_assert(&mut cs, 2);
+    }
+
+    struct Boolean;
+
+    impl Configure for Boolean {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [column] = cb.advice_columns(cs);
+            cb.assert_boolean("column is 0 or 1", column.current());
+            vec![column.0.into()]
+        }
+    }
+
+    #[test]
+    fn test_assert_boolean() {
+        let rows = |values: &[u64]| values.iter().map(|value| vec![1, *value]).collect();
+        assert_eq!(TestCircuit::<Boolean>::verify(rows(&[0, 1, 1, 0])), Ok(()));
+        assert!(TestCircuit::<Boolean>::verify(rows(&[0, 1, 2])).is_err());
+    }
+
+    struct GatedLookup;
+
+    impl Configure for GatedLookup {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [enable] = cb.binary_columns(cs);
+            let ([], [table], [value]) = cb.build_columns(cs);
+            cb.add_lookup_gated(
+                "value is in table when enabled",
+                enable.current(),
+                [value.current()],
+                [table.current()],
+            );
+            vec![enable.0.into(), table.0.into(), value.0.into()]
+        }
+    }
+
+    #[test]
+    fn test_add_lookup_gated() {
+        // The table is 0..8, and every row is enabled.
+        let rows = |values: &[(u64, u64)]| {
+            (0..8)
+                .map(|i| {
+                    let (value, enable) = values.get(i).copied().unwrap_or_default();
+                    vec![1, enable, i as u64, value]
+                })
+                .collect()
+        };
+        assert_eq!(
+            TestCircuit::<GatedLookup>::verify(rows(&[(1, 1), (3, 1), (100, 0), (200, 0)])),
+            Ok(())
+        );
+        assert!(TestCircuit::<GatedLookup>::verify(rows(&[(1, 1), (100, 1)])).is_err());
+    }
+
+    struct DefaultLookup;
+
+    impl Configure for DefaultLookup {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [enable] = cb.binary_columns(cs);
+            let ([], [table], [value]) = cb.build_columns(cs);
+            cb.condition(enable.current(), |cb| {
+                cb.add_lookup_with_default(
+                    "value is in table when enabled",
+                    [value.current()],
+                    [table.current()],
+                    [Query::from(5)],
+                )
+            });
+            vec![enable.0.into(), table.0.into(), value.0.into()]
+        }
+    }
+
+    #[test]
+    fn test_add_lookup_with_default() {
+        // The table is 1..=8, and only the rows with values are enabled.
+        let rows = |values: &[(u64, u64)]| {
+            (0..8)
+                .map(|i| match values.get(i) {
+                    Some((value, enable)) => vec![1, *enable, i as u64 + 1, *value],
+                    None => vec![0, 0, i as u64 + 1, 0],
+                })
+                .collect()
+        };
+        assert_eq!(
+            TestCircuit::<DefaultLookup>::verify(rows(&[(1, 1), (8, 1), (100, 0), (0, 0)])),
+            Ok(())
+        );
+        assert!(TestCircuit::<DefaultLookup>::verify(rows(&[(1, 1), (100, 1)])).is_err());
+    }
+
+    #[derive(Clone, Default, Debug)]
+    struct CopyCircuit {
+        value: u64,
+    }
+
+    impl Circuit<Fr> for CopyCircuit {
+        type Config = (SelectorColumn, BinaryColumn, AdviceColumn);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let selector = SelectorColumn(cs.fixed_column());
+            let mut cb = ConstraintBuilder::new(selector);
+            let [binary] = cb.binary_columns(cs);
+            let [advice] = cb.advice_columns(cs);
+            cs.enable_equality(binary.0);
+            cs.enable_equality(advice.0);
+            cb.build(cs);
+            (selector, binary, advice)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            let (selector, binary, advice) = config;
+            layouter.assign_region(
+                || "",
+                |mut region| {
+                    selector.enable(&mut region, 0);
+                    let binary_cell = binary.assign_returning_cell(&mut region, 0, true);
+                    let advice_cell = advice.assign_returning_cell(&mut region, 1, self.value);
+                    region.constrain_equal(binary_cell, advice_cell)
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn test_assign_returning_cell() {
+        let circuit = CopyCircuit { value: 1 };
+        let prover = MockProver::<Fr>::run(8, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let circuit = CopyCircuit { value: 0 };
+        let prover = MockProver::<Fr>::run(8, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[derive(Clone, Debug)]
+    struct UnknownValueCircuit {
+        value: Value<Fr>,
+    }
+
+    impl Circuit<Fr> for UnknownValueCircuit {
+        type Config = (SelectorColumn, AdviceColumn);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self {
+                value: Value::unknown(),
+            }
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let selector = SelectorColumn(cs.fixed_column());
+            let mut cb = ConstraintBuilder::new(selector);
+            let [column] = cb.advice_columns(cs);
+            cb.assert_equal("column is 7", column.current(), Query::from(7));
+            cb.build(cs);
+            (selector, column)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            let (selector, column) = config;
+            layouter.assign_region(
+                || "",
+                |mut region| {
+                    selector.enable(&mut region, 0);
+                    column.assign_value(&mut region, 0, self.value);
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn test_assign_value() {
+        let circuit = UnknownValueCircuit {
+            value: Value::known(Fr::from(7)),
+        };
+        let prover = MockProver::<Fr>::run(8, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let params = ParamsKZG::<Bn256>::setup(8, ChaCha20Rng::seed_from_u64(2));
+        keygen_vk(&params, &circuit.without_witnesses()).unwrap();
+    }
+
+    #[test]
+    fn test_add_constant_within() {
+        // selector, active, column
+        let rows = vec![
+            vec![0, 0, 0],
+            vec![1, 0, 5],
+            vec![1, 1, 5],
+            vec![1, 1, 5],
+            vec![1, 0, 8],
+            vec![1, 0, 9],
+        ];
+        assert_eq!(TestCircuit::<ConstantWithin>::verify(rows), Ok(()));
+    }
+
+    #[test]
+    fn test_add_constant_within_rejects_change_while_active() {
+        let rows = vec![
+            vec![0, 0, 0],
+            vec![1, 0, 5],
+            vec![1, 1, 5],
+            vec![1, 1, 6],
+            vec![1, 0, 8],
+            vec![1, 0, 9],
+        ];
+        assert!(TestCircuit::<ConstantWithin>::verify(rows).is_err());
+    }
+
+    #[test]
+    fn test_first_enabled_row() {
+        // The selector is enabled on every row but the first.
+        let rows = |values: &[u64]| {
+            values
+                .iter()
+                .enumerate()
+                .map(|(offset, value)| vec![u64::from(offset != 0), *value])
+                .collect()
+        };
+        assert_eq!(
+            TestCircuit::<FirstEnabledRow>::verify(rows(&[0, 1, 0, 0])),
+            Ok(())
+        );
+        assert!(TestCircuit::<FirstEnabledRow>::verify(rows(&[1, 0, 1, 1])).is_err());
+    }
+
+    #[test]
+    fn test_add_membership() {
+        let rows = |values: &[u64]| values.iter().map(|value| vec![1, *value]).collect();
+        assert_eq!(
+            TestCircuit::<Membership>::verify(rows(&[1, 3, 5, 3])),
+            Ok(())
+        );
+        assert!(TestCircuit::<Membership>::verify(rows(&[1, 2, 5])).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use super::{BinaryQuery, ConstraintBuilder, Query};
 use halo2_proofs::{
-    circuit::{Region, Value},
+    circuit::{Cell, Region, Value},
     halo2curves::ff::FromUniformBytes,
     plonk::ConstraintSystem,
     plonk::{Advice, Column},
@@ -31,9 +31,9 @@ impl BinaryColumn {
         cb: &mut ConstraintBuilder<F>,
     ) -> Self {
         let binary_column = Self(cs.advice_column());
-        cb.assert(
+        cb.assert_boolean(
             "binary column is 0 or 1",
-            binary_column.current().or(!binary_column.current()),
+            binary_column.current::<F>().into(),
         );
         binary_column
     }
@@ -44,6 +44,16 @@ impl BinaryColumn {
         offset: usize,
         value: bool,
     ) {
+        self.assign_returning_cell(region, offset, value);
+    }
+
+    /// Same as `assign`, but returns the cell for use in copy constraints.
+    pub fn assign_returning_cell<F: FromUniformBytes<64> + Ord>(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        value: bool,
+    ) -> Cell {
         region
             .assign_advice(
                 || "binary",
@@ -51,6 +61,7 @@ impl BinaryColumn {
                 offset,
                 || Value::known(F::from(value as u64)),
             )
-            .expect("failed assign_advice");
+            .expect("failed assign_advice")
+            .cell()
     }
 }
@@ -27,6 +27,10 @@ impl<F: FromUniformBytes<64> + Ord> BinaryQuery<F> {
         !((!self).and(!other))
     }
 
+    pub fn xor(self, other: Self) -> Self {
+        Self(self.0.clone() + other.0.clone() - self.0 * other.0 * 2u64)
+    }
+
     pub fn condition(self, constraint: Query<F>) -> Query<F> {
         self.0 * constraint
     }
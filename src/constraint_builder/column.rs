@@ -1,7 +1,7 @@
 use super::{BinaryQuery, Query};
 use halo2_proofs::plonk::Assigned;
 use halo2_proofs::{
-    circuit::{Region, Value},
+    circuit::{Cell, Region, Value},
     halo2curves::ff::FromUniformBytes,
     plonk::{Advice, Column, Fixed},
 };
@@ -92,6 +92,31 @@ impl AdviceColumn {
         value: T,
     ) where
         <T as TryInto<F>>::Error: Debug,
+    {
+        self.assign_value(region, offset, Value::known(value.try_into().unwrap()));
+    }
+
+    /// Same as `assign`, but also accepts unknown values, e.g. during keygen.
+    pub fn assign_value<F: FromUniformBytes<64> + Ord>(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        value: Value<F>,
+    ) {
+        region
+            .assign_advice(|| "advice", self.0, offset, || value)
+            .expect("failed assign_advice");
+    }
+
+    /// Same as `assign`, but returns the cell for use in copy constraints.
+    pub fn assign_returning_cell<F: FromUniformBytes<64> + Ord, T: Copy + TryInto<F>>(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        value: T,
+    ) -> Cell
+    where
+        <T as TryInto<F>>::Error: Debug,
     {
         region
             .assign_advice(
@@ -100,7 +125,8 @@ impl AdviceColumn {
                 offset,
                 || Value::known(value.try_into().unwrap()),
             )
-            .expect("failed assign_advice");
+            .expect("failed assign_advice")
+            .cell()
     }
 
     pub fn assign_rational<F: FromUniformBytes<64> + Ord>(
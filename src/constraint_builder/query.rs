@@ -42,9 +42,32 @@ impl<F: FromUniformBytes<64> + Ord> Query<F> {
         }
     }
 
+    /// The degree of the polynomial this query represents in the circuit's columns.
+    pub fn degree(&self) -> usize {
+        match self {
+            Query::Constant(_) | Query::Challenge(_) => 0,
+            Query::Advice(..) | Query::Fixed(..) => 1,
+            Query::Neg(q) => q.degree(),
+            Query::Add(q, u) => q.degree().max(u.degree()),
+            Query::Mul(q, u) => q.degree() + u.degree(),
+        }
+    }
+
     pub fn square(self) -> Self {
         self.clone() * self
     }
+
+    /// Random linear combination of `parts`, with the first part multiplied by the highest power
+    /// of `randomness`. This is the constraint level counterpart of `util::rlc`.
+    pub fn rlc(parts: &[Self], randomness: Self) -> Self {
+        parts.iter().fold(Self::zero(), |acc, part| {
+            acc * randomness.clone() + part.clone()
+        })
+    }
+
+    pub fn select(condition: BinaryQuery<F>, if_true: Self, if_false: Self) -> Self {
+        condition.select(if_true, if_false)
+    }
 }
 
 impl<F: FromUniformBytes<64> + Ord> From<u64> for Query<F> {
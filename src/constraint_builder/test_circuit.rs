@@ -0,0 +1,86 @@
+use super::{ConstraintBuilder, SelectorColumn};
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    dev::{MockProver, VerifyFailure},
+    halo2curves::bn256::Fr,
+    plonk::{Any, Circuit, Column, ConstraintSystem, Error},
+};
+use itertools::Itertools;
+use std::marker::PhantomData;
+
+/// Adds the columns and constraints of a `TestCircuit`.
+pub trait Configure {
+    /// Returns the columns which the values in each row are assigned to, after the every row
+    /// selector.
+    fn configure(cs: &mut ConstraintSystem<Fr>, cb: &mut ConstraintBuilder<Fr>)
+        -> Vec<Column<Any>>;
+}
+
+/// Circuit with the constraints added by `C`, in which `rows[offset]` holds the value of the every
+/// row selector and then the values of the columns returned by `C::configure` at `offset`.
+pub struct TestCircuit<C, T = u64> {
+    rows: Vec<Vec<T>>,
+    configure: PhantomData<C>,
+}
+
+impl<C: Configure, T: Copy + Into<Fr>> TestCircuit<C, T> {
+    pub fn verify(rows: Vec<Vec<T>>) -> Result<(), Vec<VerifyFailure>> {
+        let circuit = Self {
+            rows,
+            configure: PhantomData,
+        };
+        MockProver::<Fr>::run(8, &circuit, vec![]).unwrap().verify()
+    }
+}
+
+impl<C: Configure, T: Copy + Into<Fr>> Circuit<Fr> for TestCircuit<C, T> {
+    type Config = Vec<Column<Any>>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            rows: vec![],
+            configure: PhantomData,
+        }
+    }
+
+    fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let selector = SelectorColumn(cs.fixed_column());
+        let mut cb = ConstraintBuilder::new(selector);
+        let columns = C::configure(cs, &mut cb);
+        cb.build(cs);
+        std::iter::once(selector.0.into()).chain(columns).collect()
+    }
+
+    fn synthesize(
+        &self,
+        columns: Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "",
+            |mut region| {
+                for (offset, row) in self.rows.iter().enumerate() {
+                    for (column, value) in columns.iter().zip_eq(row) {
+                        let value = Value::known((*value).into());
+                        match column.column_type() {
+                            Any::Fixed => region.assign_fixed(
+                                || "",
+                                (*column).try_into().unwrap(),
+                                offset,
+                                || value,
+                            )?,
+                            _ => region.assign_advice(
+                                || "",
+                                (*column).try_into().unwrap(),
+                                offset,
+                                || value,
+                            )?,
+                        };
+                    }
+                }
+                Ok(())
+            },
+        )
+    }
+}
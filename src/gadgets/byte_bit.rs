@@ -15,6 +15,32 @@ pub trait RangeCheck8Lookup {
 
 pub trait RangeCheck256Lookup {
     fn lookup<F: FromUniformBytes<64> + Ord>(&self) -> [Query<F>; 1];
+
+    /// Constrains 0 <= value < 16.
+    fn range_check_nibble<F: FromUniformBytes<64> + Ord>(
+        &self,
+        cb: &mut ConstraintBuilder<F>,
+        value: Query<F>,
+    ) {
+        self.range_check_n_bits(cb, value, 4)
+    }
+
+    /// Constrains 0 <= value < 2^n for n <= 8, by checking that both value and
+    /// value * 2^(8 - n) fit into a byte.
+    fn range_check_n_bits<F: FromUniformBytes<64> + Ord>(
+        &self,
+        cb: &mut ConstraintBuilder<F>,
+        value: Query<F>,
+        n: u32,
+    ) {
+        assert!(n <= 8, "can only range check up to 8 bits");
+        cb.add_lookup("0 <= value < 256", [value.clone()], self.lookup());
+        cb.add_lookup(
+            "0 <= value * 2^(8 - n) < 256",
+            [value * (1u64 << (8 - n))],
+            self.lookup(),
+        );
+    }
 }
 
 pub trait ByteBitLookup {
@@ -76,3 +102,90 @@ impl ByteBitLookup for ByteBitGadget {
         ]
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constraint_builder::{AdviceColumn, SelectorColumn};
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        dev::MockProver,
+        halo2curves::bn256::Fr,
+        plonk::{Circuit, Error},
+    };
+
+    #[derive(Clone, Default, Debug)]
+    struct TestCircuit {
+        nibbles: Vec<u64>,
+        five_bit_values: Vec<u64>,
+    }
+
+    impl Circuit<Fr> for TestCircuit {
+        type Config = (SelectorColumn, ByteBitGadget, AdviceColumn, AdviceColumn);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let selector = SelectorColumn(cs.fixed_column());
+            let mut cb = ConstraintBuilder::new(selector);
+
+            let byte_bit = ByteBitGadget::configure(cs, &mut cb);
+            let [nibble, five_bit_value] = cb.advice_columns(cs);
+            byte_bit.range_check_nibble(&mut cb, nibble.current());
+            byte_bit.range_check_n_bits(&mut cb, five_bit_value.current(), 5);
+            cb.build(cs);
+            (selector, byte_bit, nibble, five_bit_value)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            let (selector, byte_bit, nibble, five_bit_value) = config;
+            layouter.assign_region(
+                || "",
+                |mut region| {
+                    for offset in 1..ByteBitGadget::n_rows_required() {
+                        selector.enable(&mut region, offset);
+                    }
+                    byte_bit.assign(&mut region);
+                    for (offset, value) in self.nibbles.iter().enumerate() {
+                        nibble.assign(&mut region, 1 + offset, *value);
+                    }
+                    for (offset, value) in self.five_bit_values.iter().enumerate() {
+                        five_bit_value.assign(&mut region, 1 + offset, *value);
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn test_range_check_n_bits() {
+        let circuit = TestCircuit {
+            nibbles: vec![0, 1, 15],
+            five_bit_values: vec![0, 16, 31],
+        };
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let circuit = TestCircuit {
+            nibbles: vec![16],
+            five_bit_values: vec![],
+        };
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+
+        let circuit = TestCircuit {
+            nibbles: vec![],
+            five_bit_values: vec![32],
+        };
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
@@ -237,6 +237,62 @@ mod test {
         }
     }
 
+    #[derive(Clone, Default, Debug)]
+    struct FixedRandomnessCircuit {
+        u32s: Vec<u32>,
+    }
+
+    impl Circuit<Fr> for FixedRandomnessCircuit {
+        type Config = (
+            SelectorColumn,
+            ByteBitGadget,
+            ByteRepresentationConfig,
+            RlcRandomness,
+        );
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let selector = SelectorColumn(cs.fixed_column());
+            let mut cb = ConstraintBuilder::new(selector);
+
+            let byte_bit = ByteBitGadget::configure(cs, &mut cb);
+            let randomness = RlcRandomness::fixed(cs, 11);
+            let byte_representation =
+                ByteRepresentationConfig::configure(cs, &mut cb, &byte_bit, &randomness);
+            cb.add_lookup(
+                "rlc of 0x00010203 is ((1 * 11) + 2) * 11 + 3 = 146",
+                [Query::from(0x010203), Query::from(3), Query::from(146)],
+                RlcLookup::lookup(&byte_representation),
+            );
+            cb.build(cs);
+            (selector, byte_bit, byte_representation, randomness)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            let (selector, byte_bit, byte_representation, rlc_randomness) = config;
+            let randomness = rlc_randomness.value(&layouter);
+            layouter.assign_region(
+                || "",
+                |mut region| {
+                    for offset in 0..(8 * 256) {
+                        selector.enable(&mut region, offset);
+                    }
+                    byte_bit.assign(&mut region);
+                    byte_representation.assign(&mut region, &self.u32s, &[], &[], &[], randomness);
+                    Ok(())
+                },
+            )
+        }
+    }
+
     #[test]
     fn test_byte_representation() {
         let circuit = TestCircuit {
@@ -249,6 +305,21 @@ mod test {
         assert_eq!(prover.verify(), Ok(()));
     }
 
+    #[test]
+    fn test_rlc_with_fixed_randomness() {
+        let circuit = FixedRandomnessCircuit {
+            u32s: vec![0x010203],
+        };
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let circuit = FixedRandomnessCircuit {
+            u32s: vec![0x010204],
+        };
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
     #[test]
     fn test_helpers() {
         let mut x = vec![0; 8];
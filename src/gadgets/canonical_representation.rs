@@ -65,9 +65,10 @@ impl CanonicalRepresentationConfig {
             cb.assert_equal(
                 "every group of 32 bytes represent value",
                 value.current(),
-                (0..32)
-                    .map(|i| byte.rotation(i))
-                    .fold(Query::zero(), |acc, x| acc * 256 + x),
+                Query::rlc(
+                    &(0..32).map(|i| byte.rotation(i)).collect::<Vec<_>>(),
+                    Query::from(256),
+                ),
             );
             cb.assert(
                 "differences_are_zero_so_far = 1 when index = 0",
@@ -69,3 +69,107 @@ impl IsZeroGadget {
         }
     }
 }
+
+/// IsZeroGadget on the difference of two values.
+#[derive(Clone, Copy)]
+pub struct IsEqualGadget {
+    difference: IsZeroGadget,
+}
+
+impl IsEqualGadget {
+    pub fn current<F: FromUniformBytes<64> + Ord>(self) -> BinaryQuery<F> {
+        self.difference.current()
+    }
+
+    pub fn assign<F: FromUniformBytes<64> + Ord>(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        left: F,
+        right: F,
+    ) {
+        self.difference
+            .assign_value_and_inverse(region, offset, left - right);
+    }
+
+    pub fn configure<F: FromUniformBytes<64> + Ord>(
+        cs: &mut ConstraintSystem<F>,
+        cb: &mut ConstraintBuilder<F>,
+        left: Query<F>,
+        right: Query<F>,
+    ) -> Self {
+        let [difference] = cb.advice_columns(cs);
+        cb.assert_equal(
+            "difference = left - right",
+            difference.current(),
+            left - right,
+        );
+        Self {
+            difference: IsZeroGadget::configure(cs, cb, difference),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constraint_builder::test_circuit::{Configure, TestCircuit};
+    use halo2_proofs::{
+        halo2curves::{bn256::Fr, ff::Field},
+        plonk::{Any, Column},
+    };
+
+    struct IsEqual;
+
+    impl Configure for IsEqual {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [left, right, is_equal] = cb.advice_columns(cs);
+            let is_equal_gadget = IsEqualGadget::configure(cs, cb, left.current(), right.current());
+            cb.assert_equal(
+                "is_equal matches gadget",
+                is_equal.current(),
+                is_equal_gadget.current().into(),
+            );
+            let IsZeroGadget {
+                value,
+                inverse_or_zero,
+            } = is_equal_gadget.difference;
+            [left, right, is_equal, value, inverse_or_zero]
+                .map(|column| column.0.into())
+                .to_vec()
+        }
+    }
+
+    #[test]
+    fn test_is_equal() {
+        // (left, right, claimed left == right)
+        let rows = |rows: &[(u64, u64, bool)]| {
+            rows.iter()
+                .map(|&(left, right, claim)| {
+                    let difference = Fr::from(left) - Fr::from(right);
+                    vec![
+                        Fr::one(),
+                        Fr::from(left),
+                        Fr::from(right),
+                        Fr::from(u64::from(claim)),
+                        difference,
+                        difference.invert().unwrap_or(Fr::zero()),
+                    ]
+                })
+                .collect()
+        };
+        assert_eq!(
+            TestCircuit::<IsEqual, _>::verify(rows(&[
+                (3, 3, true),
+                (3, 4, false),
+                (0, 0, true),
+                (0, 7, false)
+            ])),
+            Ok(())
+        );
+        assert!(TestCircuit::<IsEqual, _>::verify(rows(&[(3, 4, true)])).is_err());
+    }
+}
@@ -13,6 +13,30 @@ use itertools::Itertools;
 
 pub trait KeyBitLookup {
     fn lookup<F: FromUniformBytes<64> + Ord>(&self) -> [Query<F>; 3];
+
+    /// Constrains `bit` to be bit `index` of `key`, where `index` can vary from row to row.
+    /// Indices >= 256 have no valid assignment, and `bit` must be 0 for indices 254 and 255,
+    /// which are past the highest bit of a canonical field element.
+    fn lookup_at<F: FromUniformBytes<64> + Ord>(
+        &self,
+        cb: &mut ConstraintBuilder<F>,
+        range_check_256: &impl RangeCheck256Lookup,
+        key: Query<F>,
+        index: Query<F>,
+        bit: Query<F>,
+    ) {
+        cb.add_lookup(
+            "bit = key.bit(index)",
+            [key, index.clone(), bit.clone()],
+            self.lookup(),
+        );
+        // The key bit table only has indices < 256, so if bit is 1 this forces index <= 253.
+        cb.add_lookup(
+            "bit is 0 if index >= 254",
+            [bit * (Query::from(253) - index)],
+            range_check_256.lookup(),
+        );
+    }
 }
 
 #[derive(Clone)]
@@ -185,6 +209,7 @@ mod test {
     use super::*;
     use crate::constraint_builder::SelectorColumn;
     use halo2_proofs::{
+        arithmetic::Field,
         circuit::{Layouter, SimpleFloorPlanner},
         dev::MockProver,
         plonk::{Circuit, Error},
@@ -193,6 +218,8 @@ mod test {
     #[derive(Clone, Default, Debug)]
     struct TestCircuit {
         lookups: Vec<(Fr, usize, bool)>,
+        // Checked against the key bit table with lookup_at.
+        queries: Vec<(Fr, usize, bool)>,
     }
 
     impl Circuit<Fr> for TestCircuit {
@@ -202,6 +229,7 @@ mod test {
             ByteBitGadget,
             CanonicalRepresentationConfig,
             RlcRandomness,
+            [AdviceColumn; 3],
         );
         type FloorPlanner = SimpleFloorPlanner;
 
@@ -225,6 +253,14 @@ mod test {
                 &byte_bit,
                 &byte_bit,
             );
+            let [key, index, bit] = cb.advice_columns(cs);
+            key_bit.lookup_at(
+                &mut cb,
+                &byte_bit,
+                key.current(),
+                index.current(),
+                bit.current(),
+            );
             cb.build(cs);
             (
                 selector,
@@ -232,6 +268,7 @@ mod test {
                 byte_bit,
                 canonical_representation,
                 randomness,
+                [key, index, bit],
             )
         }
 
@@ -242,7 +279,14 @@ mod test {
         ) -> Result<(), Error> {
             let keys: Vec<_> = self.lookups.iter().map(|lookup| lookup.0).collect();
 
-            let (selector, key_bit, byte_bit, canonical_representation, rlc_randomness) = config;
+            let (
+                selector,
+                key_bit,
+                byte_bit,
+                canonical_representation,
+                rlc_randomness,
+                [key, index, bit],
+            ) = config;
             let randomness = rlc_randomness.value(&layouter);
 
             layouter.assign_region(
@@ -255,6 +299,11 @@ mod test {
                     key_bit.assign(&mut region, &self.lookups);
                     byte_bit.assign(&mut region);
                     canonical_representation.assign(&mut region, randomness, &keys, 256);
+                    for (offset, (value, i, b)) in self.queries.iter().enumerate() {
+                        key.assign(&mut region, 1 + offset, *value);
+                        index.assign(&mut region, 1 + offset, *i as u64);
+                        bit.assign(&mut region, 1 + offset, *b);
+                    }
                     Ok(())
                 },
             )
@@ -270,8 +319,35 @@ mod test {
                 (Fr::from(2342341), 10, true),
                 (Fr::from(2342341), 255, false),
             ],
+            queries: vec![],
+        };
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_lookup_at() {
+        let key = Fr::from(0b1011) + Fr::from(2).pow([253]);
+        let lookups = vec![
+            (key, 0, true),
+            (key, 2, false),
+            (key, 253, true),
+            (key, 254, false),
+        ];
+        let circuit = TestCircuit {
+            lookups: lookups.clone(),
+            queries: lookups.clone(),
         };
         let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
         assert_eq!(prover.verify(), Ok(()));
+
+        for query in [(key, 254, true), (key, 255, true), (key, 256, false)] {
+            let circuit = TestCircuit {
+                lookups: lookups.clone(),
+                queries: vec![query],
+            };
+            let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+            assert!(prover.verify().is_err(), "{query:?}");
+        }
     }
 }
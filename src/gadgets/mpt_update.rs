@@ -46,6 +46,8 @@ lazy_static! {
 }
 
 pub trait MptUpdateLookup<F: FromUniformBytes<64> + Ord> {
+    /// Returns `[address, storage_key_rlc, proof_type, new_root_rlc, old_root_rlc, new_value,
+    /// old_value]`, all of which are 0 on rows which do not start an mpt update.
     fn lookup(&self) -> [Query<F>; 7];
 }
 
@@ -72,9 +74,9 @@ pub struct MptUpdateConfig {
     direction: AdviceColumn,
     sibling: AdviceColumn,
 
-    intermediate_values: [AdviceColumn; 10], // can be 4?
-    second_phase_intermediate_values: [SecondPhaseAdviceColumn; 10], // 4?
-    is_zero_gadgets: [IsZeroGadget; 4],      // can be 3
+    intermediate_values: [AdviceColumn; 5],
+    second_phase_intermediate_values: [SecondPhaseAdviceColumn; 4],
+    is_zero_gadgets: [IsZeroGadget; 4], // can be 3
 }
 
 impl<F: FromUniformBytes<64> + Ord> MptUpdateLookup<F> for MptUpdateConfig {
@@ -120,8 +122,8 @@ impl MptUpdateConfig {
         let [domain, old_hash, new_hash, depth, key, other_key, direction, sibling] =
             cb.advice_columns(cs);
 
-        let intermediate_values: [AdviceColumn; 10] = cb.advice_columns(cs);
-        let second_phase_intermediate_values: [SecondPhaseAdviceColumn; 10] =
+        let intermediate_values: [AdviceColumn; 5] = cb.advice_columns(cs);
+        let second_phase_intermediate_values: [SecondPhaseAdviceColumn; 4] =
             cb.second_phase_advice_columns(cs);
         let is_zero_gadgets = cb
             .advice_columns(cs)
@@ -136,6 +138,9 @@ impl MptUpdateConfig {
             is_start.clone().into(),
             path_type.current_matches(&[PathType::Start]).into(),
         );
+        cb.condition(cb.first_enabled_row(), |cb| {
+            cb.assert("first enabled row starts an update", is_start.clone());
+        });
         cb.condition(is_start.clone().and(cb.every_row_selector()), |cb| {
             let [address_high, address_low, ..] = intermediate_values;
             let [old_hash_rlc, new_hash_rlc, ..] = second_phase_intermediate_values;
@@ -193,20 +198,17 @@ impl MptUpdateConfig {
             );
         });
 
-        cb.condition(
-            !segment_type.current_matches(&[SegmentType::Start, SegmentType::AccountLeaf3]),
-            |cb| {
-                cb.assert_equal(
-                    "key can only change on Start or AccountLeaf3 rows",
-                    key.current(),
-                    key.previous(),
-                );
-                cb.assert_equal(
-                    "other_key can only change on Start or AccountLeaf3 rows",
-                    other_key.current(),
-                    other_key.previous(),
-                );
-            },
+        let is_key_constant =
+            !segment_type.current_matches(&[SegmentType::Start, SegmentType::AccountLeaf3]);
+        cb.add_constant_within(
+            "key can only change on Start or AccountLeaf3 rows",
+            is_key_constant.clone(),
+            key,
+        );
+        cb.add_constant_within(
+            "other_key can only change on Start or AccountLeaf3 rows",
+            is_key_constant,
+            other_key,
         );
 
         let is_trie =
@@ -283,13 +285,10 @@ impl MptUpdateConfig {
 
         for variant in SegmentType::iter() {
             let conditional_constraints = |cb: &mut ConstraintBuilder<F>| {
-                cb.assert_zero(
+                cb.add_membership(
                     "domain in allowed set for segment type",
-                    segment::domains(variant)
-                        .iter()
-                        .fold(Query::one(), |product, domain| {
-                            product * (config.domain.current() - u64::from(*domain))
-                        }),
+                    config.domain.current(),
+                    segment::domains(variant).into_iter().map(u64::from),
                 );
             };
             cb.condition(
@@ -2047,68 +2046,97 @@ fn address_low(a: Address) -> u32 {
 
 // ... the return traces: ([inp;2], domain, hash)
 pub fn hash_traces(proofs: &[Proof]) -> Vec<([Fr; 2], Fr, Fr)> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!("hash_traces", proofs = proofs.len()).entered();
     let mut hash_traces = vec![(
         [Fr::zero(), Fr::zero()],
         HashDomain::Pair.into(),
         *ZERO_PAIR_HASH,
     )];
-    for proof in proofs.iter() {
-        for (left, right, domain, hash) in proof.account_trie_rows.poseidon_lookups() {
-            hash_traces.push(([left, right], Fr::from(domain), hash));
-        }
+    #[cfg(feature = "rayon")]
+    hash_traces.extend({
+        use rayon::prelude::*;
+        proofs
+            .par_iter()
+            .flat_map_iter(proof_hash_traces)
+            .collect::<Vec<_>>()
+    });
+    #[cfg(not(feature = "rayon"))]
+    hash_traces.extend(proofs.iter().flat_map(proof_hash_traces));
+    hash_traces.sort();
+    hash_traces.dedup();
+    hash_traces
+}
 
-        hash_traces.extend(
-            proof
-                .storage
-                .poseidon_lookups()
-                .into_iter()
-                .map(|(left, right, domain, h)| ([left, right], Fr::from(domain), h)),
-        );
+/// Hash traces required by a single proof, in the order they are encountered. These are not
+/// deduplicated, so the length is an upper bound on the poseidon rows the proof adds to the table.
+pub fn proof_hash_traces(proof: &Proof) -> Vec<([Fr; 2], Fr, Fr)> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("proof_hash_traces").entered();
+    let mut hash_traces = vec![];
+    for (left, right, domain, hash) in proof.account_trie_rows.poseidon_lookups() {
+        hash_traces.push(([left, right], Fr::from(domain), hash));
+    }
 
-        let key = account_key(proof.claim.address);
+    hash_traces.extend(
+        proof
+            .storage
+            .poseidon_lookups()
+            .into_iter()
+            .map(|(left, right, domain, h)| ([left, right], Fr::from(domain), h)),
+    );
+
+    let key = account_key(proof.claim.address);
+    hash_traces.push((
+        [
+            Fr::from_u128(address_high(proof.claim.address)),
+            Fr::from_u128(u128::from(address_low(proof.claim.address)) << 96),
+        ],
+        HashDomain::Pair.into(),
+        key,
+    ));
+
+    if let Some(data_hash) = proof.old.leaf_data_hash {
         hash_traces.push((
-            [
-                Fr::from_u128(address_high(proof.claim.address)),
-                Fr::from_u128(u128::from(address_low(proof.claim.address)) << 96),
-            ],
-            HashDomain::Pair.into(),
-            key,
+            [proof.old.key, data_hash],
+            HashDomain::Leaf.into(),
+            domain_hash(proof.old.key, data_hash, HashDomain::Leaf),
         ));
+    }
+    if let Some(data_hash) = proof.new.leaf_data_hash {
+        hash_traces.push((
+            [proof.new.key, data_hash],
+            HashDomain::Leaf.into(),
+            domain_hash(proof.new.key, data_hash, HashDomain::Leaf),
+        ));
+    }
 
-        if let Some(data_hash) = proof.old.leaf_data_hash {
-            hash_traces.push((
-                [proof.old.key, data_hash],
-                HashDomain::Leaf.into(),
-                domain_hash(proof.old.key, data_hash, HashDomain::Leaf),
-            ));
-        }
-        if let Some(data_hash) = proof.new.leaf_data_hash {
-            hash_traces.push((
-                [proof.new.key, data_hash],
-                HashDomain::Leaf.into(),
-                domain_hash(proof.new.key, data_hash, HashDomain::Leaf),
-            ));
-        }
-
-        for account_leaf_hash_traces in
-            [proof.old_account_hash_traces, proof.new_account_hash_traces]
-        {
-            for [left, right, digest] in account_leaf_hash_traces {
-                if domain_hash(left, right, HashDomain::AccountFields) == digest {
-                    hash_traces.push(([left, right], HashDomain::AccountFields.into(), digest))
-                } else if domain_hash(left, right, HashDomain::Leaf) == digest {
-                    hash_traces.push(([left, right], HashDomain::Leaf.into(), digest))
-                } else if domain_hash(left, right, HashDomain::Pair) == digest {
-                    hash_traces.push(([left, right], HashDomain::Pair.into(), digest))
-                }
+    for account_leaf_hash_traces in [proof.old_account_hash_traces, proof.new_account_hash_traces] {
+        for [left, right, digest] in account_leaf_hash_traces {
+            if domain_hash(left, right, HashDomain::AccountFields) == digest {
+                hash_traces.push(([left, right], HashDomain::AccountFields.into(), digest))
+            } else if domain_hash(left, right, HashDomain::Leaf) == digest {
+                hash_traces.push(([left, right], HashDomain::Leaf.into(), digest))
+            } else if domain_hash(left, right, HashDomain::Pair) == digest {
+                hash_traces.push(([left, right], HashDomain::Pair.into(), digest))
             }
         }
     }
-    hash_traces.sort();
-    hash_traces.dedup();
     hash_traces
 }
 
+/// Sorted and deduplicated union of the hash traces of several batches of proofs, so that a
+/// single poseidon table can cover all of them.
+pub fn union_hash_traces(batches: &[Vec<Proof>]) -> Vec<([Fr; 2], Fr, Fr)> {
+    let mut union: Vec<_> = batches
+        .iter()
+        .flat_map(|proofs| hash_traces(proofs))
+        .collect();
+    union.sort();
+    union.dedup();
+    union
+}
+
 /// ...
 pub fn key_bit_lookups(proofs: &[Proof]) -> Vec<(Fr, usize, bool)> {
     let mut lookups = vec![(Fr::zero(), 0, false), (Fr::one(), 0, true)];
@@ -2146,81 +2174,35 @@ pub fn key_bit_lookups(proofs: &[Proof]) -> Vec<(Fr, usize, bool)> {
 
 /// ...
 pub fn byte_representations(proofs: &[Proof]) -> (Vec<u32>, Vec<u64>, Vec<u128>, Vec<Fr>) {
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        merge_byte_representations(proofs.par_iter().map(proof_byte_representations).collect())
+    }
+    #[cfg(not(feature = "rayon"))]
+    byte_representations_serial(proofs)
+}
+
+/// Same as `byte_representations`, but without computing the proofs in parallel.
+#[cfg(any(test, not(feature = "rayon")))]
+pub(crate) fn byte_representations_serial(
+    proofs: &[Proof],
+) -> (Vec<u32>, Vec<u64>, Vec<u128>, Vec<Fr>) {
+    merge_byte_representations(proofs.iter().map(proof_byte_representations).collect())
+}
+
+type ByteRepresentations = (Vec<u32>, Vec<u64>, Vec<u128>, Vec<Fr>);
+
+fn merge_byte_representations(representations: Vec<ByteRepresentations>) -> ByteRepresentations {
     let mut u32s = vec![];
     let mut u64s = vec![];
     let mut u128s = vec![0];
     let mut frs = vec![];
-
-    for proof in proofs {
-        u128s.push(address_high(proof.claim.address));
-        u32s.push(address_low(proof.claim.address));
-        match MPTProofType::from(proof.claim) {
-            MPTProofType::NonceChanged | MPTProofType::CodeSizeExists => {
-                u128s.push(address_high(proof.claim.address));
-                if let Some(account) = proof.old_account {
-                    u64s.push(account.nonce);
-                    u64s.push(account.code_size);
-                };
-                if let Some(account) = proof.new_account {
-                    u64s.push(account.nonce);
-                    u64s.push(account.code_size);
-                };
-            }
-            MPTProofType::BalanceChanged => {
-                u128s.push(address_high(proof.claim.address));
-                if let Some(account) = proof.old_account {
-                    frs.push(account.balance);
-                };
-                if let Some(account) = proof.new_account {
-                    frs.push(account.balance);
-                };
-            }
-            MPTProofType::PoseidonCodeHashExists => {
-                u128s.push(address_high(proof.claim.address));
-            }
-            MPTProofType::CodeHashExists => {
-                u128s.push(address_high(proof.claim.address));
-                if let Some(account) = proof.old_account {
-                    let (hi, lo) = u256_hi_lo(&account.keccak_codehash);
-                    u128s.push(hi);
-                    u128s.push(lo);
-                };
-                if let Some(account) = proof.new_account {
-                    let (hi, lo) = u256_hi_lo(&account.keccak_codehash);
-                    u128s.push(hi);
-                    u128s.push(lo);
-                };
-            }
-            MPTProofType::StorageChanged => {
-                u128s.push(address_high(proof.claim.address));
-                let (storage_key_high, storage_key_low) = u256_hi_lo(&proof.claim.storage_key());
-                u128s.push(storage_key_high);
-                u128s.push(storage_key_low);
-
-                match &proof.storage {
-                    StorageProof::Root(_) => unreachable!(),
-                    StorageProof::Update {
-                        old_leaf, new_leaf, ..
-                    } => {
-                        let (old_value_high, old_value_low) = u256_hi_lo(&old_leaf.value());
-                        let (new_value_high, new_value_low) = u256_hi_lo(&new_leaf.value());
-                        u128s.extend(vec![
-                            old_value_high,
-                            old_value_low,
-                            new_value_high,
-                            new_value_low,
-                        ]);
-                    }
-                }
-            }
-            MPTProofType::StorageDoesNotExist => {
-                u128s.push(address_high(proof.claim.address));
-                let (storage_key_high, storage_key_low) = u256_hi_lo(&proof.claim.storage_key());
-                u128s.push(storage_key_high);
-                u128s.push(storage_key_low);
-            }
-            _ => {}
-        }
+    for (proof_u32s, proof_u64s, proof_u128s, proof_frs) in representations {
+        u32s.extend(proof_u32s);
+        u64s.extend(proof_u64s);
+        u128s.extend(proof_u128s);
+        frs.extend(proof_frs);
     }
 
     u32s.sort();
@@ -2238,6 +2220,85 @@ pub fn byte_representations(proofs: &[Proof]) -> (Vec<u32>, Vec<u64>, Vec<u128>,
     (u32s, u64s, u128s, frs)
 }
 
+fn proof_byte_representations(proof: &Proof) -> ByteRepresentations {
+    let mut u32s = vec![];
+    let mut u64s = vec![];
+    let mut u128s = vec![];
+    let mut frs = vec![];
+
+    u128s.push(address_high(proof.claim.address));
+    u32s.push(address_low(proof.claim.address));
+    match MPTProofType::from(proof.claim) {
+        MPTProofType::NonceChanged | MPTProofType::CodeSizeExists => {
+            u128s.push(address_high(proof.claim.address));
+            if let Some(account) = proof.old_account {
+                u64s.push(account.nonce);
+                u64s.push(account.code_size);
+            };
+            if let Some(account) = proof.new_account {
+                u64s.push(account.nonce);
+                u64s.push(account.code_size);
+            };
+        }
+        MPTProofType::BalanceChanged => {
+            u128s.push(address_high(proof.claim.address));
+            if let Some(account) = proof.old_account {
+                frs.push(account.balance);
+            };
+            if let Some(account) = proof.new_account {
+                frs.push(account.balance);
+            };
+        }
+        MPTProofType::PoseidonCodeHashExists => {
+            u128s.push(address_high(proof.claim.address));
+        }
+        MPTProofType::CodeHashExists => {
+            u128s.push(address_high(proof.claim.address));
+            if let Some(account) = proof.old_account {
+                let (hi, lo) = u256_hi_lo(&account.keccak_codehash);
+                u128s.push(hi);
+                u128s.push(lo);
+            };
+            if let Some(account) = proof.new_account {
+                let (hi, lo) = u256_hi_lo(&account.keccak_codehash);
+                u128s.push(hi);
+                u128s.push(lo);
+            };
+        }
+        MPTProofType::StorageChanged => {
+            u128s.push(address_high(proof.claim.address));
+            let (storage_key_high, storage_key_low) = u256_hi_lo(&proof.claim.storage_key());
+            u128s.push(storage_key_high);
+            u128s.push(storage_key_low);
+
+            match &proof.storage {
+                StorageProof::Root(_) => unreachable!(),
+                StorageProof::Update {
+                    old_leaf, new_leaf, ..
+                } => {
+                    let (old_value_high, old_value_low) = u256_hi_lo(&old_leaf.value());
+                    let (new_value_high, new_value_low) = u256_hi_lo(&new_leaf.value());
+                    u128s.extend(vec![
+                        old_value_high,
+                        old_value_low,
+                        new_value_high,
+                        new_value_low,
+                    ]);
+                }
+            }
+        }
+        MPTProofType::StorageDoesNotExist => {
+            u128s.push(address_high(proof.claim.address));
+            let (storage_key_high, storage_key_low) = u256_hi_lo(&proof.claim.storage_key());
+            u128s.push(storage_key_high);
+            u128s.push(storage_key_low);
+        }
+        _ => {}
+    }
+
+    (u32s, u64s, u128s, frs)
+}
+
 /// ..
 pub fn mpt_update_keys(proofs: &[Proof]) -> Vec<Fr> {
     let mut keys = vec![Fr::zero(), Fr::one()];
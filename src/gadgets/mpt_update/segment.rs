@@ -156,3 +156,39 @@ pub fn domains(segment_type: SegmentType) -> Vec<HashDomain> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn leaf_segments_do_not_continue_traversal() {
+        let leaf_segments = [
+            SegmentType::AccountLeaf0,
+            SegmentType::AccountLeaf1,
+            SegmentType::AccountLeaf2,
+            SegmentType::AccountLeaf3,
+            SegmentType::StorageLeaf0,
+        ];
+        for proof_type in MPTProofType::iter() {
+            let transitions = transitions(proof_type);
+            for leaf_segment in leaf_segments {
+                for next in transitions.get(&leaf_segment).into_iter().flatten() {
+                    assert_ne!(
+                        *next,
+                        SegmentType::AccountTrie,
+                        "{proof_type:?}: {leaf_segment:?} -> AccountTrie"
+                    );
+                    if leaf_segment == SegmentType::StorageLeaf0 {
+                        assert_eq!(
+                            *next,
+                            SegmentType::Start,
+                            "{proof_type:?}: StorageLeaf0 must end the update"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -21,9 +21,9 @@ impl<T: IntoEnumIterator + Hash + Eq + PartialOrd + Ord> OneHot<T> {
             columns.insert(variant, cb.binary_columns::<1>(cs)[0]);
         }
         let config = Self { columns };
-        cb.assert(
+        cb.assert_boolean(
             "sum of binary columns in OneHot is 0 or 1",
-            config.sum(0).or(!config.sum(0)),
+            config.sum::<F>(0).into(),
         );
         config
     }
@@ -98,3 +98,69 @@ impl<T: IntoEnumIterator + Hash + Eq + PartialOrd + Ord> OneHot<T> {
         variants
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constraint_builder::test_circuit::{Configure, TestCircuit};
+    use halo2_proofs::{
+        halo2curves::bn256::Fr,
+        plonk::{Any, Column},
+    };
+    use strum_macros::EnumIter;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, EnumIter, Hash)]
+    enum Variant {
+        A,
+        B,
+        C,
+    }
+
+    struct OneHotVariant;
+
+    impl Configure for OneHotVariant {
+        fn configure(
+            cs: &mut ConstraintSystem<Fr>,
+            cb: &mut ConstraintBuilder<Fr>,
+        ) -> Vec<Column<Any>> {
+            let [index, is_match] = cb.advice_columns(cs);
+            let one_hot = OneHot::configure(cs, cb);
+            cb.assert_equal("index matches one hot", index.current(), one_hot.current());
+            cb.assert_equal(
+                "is_match matches one hot",
+                is_match.current(),
+                one_hot.current_matches(&[Variant::B, Variant::C]).into(),
+            );
+            [index.0, is_match.0]
+                .into_iter()
+                .chain(one_hot.columns.values().map(|column| column.0))
+                .map(Column::<Any>::from)
+                .collect()
+        }
+    }
+
+    // selector, index, is_match for [B, C], B column, C column
+    const A: [u64; 5] = [1, 0, 0, 0, 0];
+    const B: [u64; 5] = [1, 1, 1, 1, 0];
+    const C: [u64; 5] = [1, 2, 1, 0, 1];
+
+    #[test]
+    fn test_one_hot() {
+        let rows = [A, B, C].map(Vec::from).to_vec();
+        assert_eq!(TestCircuit::<OneHotVariant>::verify(rows), Ok(()));
+    }
+
+    #[test]
+    fn test_one_hot_wrong_match() {
+        let mut row = A;
+        row[2] = 1;
+        assert!(TestCircuit::<OneHotVariant>::verify(vec![row.to_vec()]).is_err());
+    }
+
+    #[test]
+    fn test_one_hot_two_columns_set() {
+        let mut row = B;
+        row[4] = 1;
+        assert!(TestCircuit::<OneHotVariant>::verify(vec![row.to_vec()]).is_err());
+    }
+}
@@ -5,10 +5,17 @@ use halo2_proofs::{circuit::Region, halo2curves::bn256::Fr, plonk::ConstraintSys
 #[cfg(any(test, feature = "bench"))]
 use hash_circuit::hash::Hashable;
 
+/// The test poseidon table is padded to at least this many rows, so that its fixed column does
+/// not depend on the number of hashes looked up.
 #[cfg(any(test, feature = "bench"))]
-const MAX_POSEIDON_ROWS: usize = 200;
+pub const MAX_POSEIDON_ROWS: usize = 200;
 
 /// Lookup  represent the poseidon table in zkevm circuit
+///
+/// Any poseidon circuit can be used by the mpt circuit as long as it exposes its table in this
+/// layout: `(q_enable, [hash, left, right, control, domain_spec, head_mark])`. The mpt circuit
+/// only hashes two field elements at a time, so its lookups always have `q_enable = 1`,
+/// `control = 0` and `head_mark = 1`.
 pub trait PoseidonLookup {
     fn lookup_columns(&self) -> (FixedColumn, [AdviceColumn; 6]) {
         let (fixed, adv) = self.lookup_columns_generic();
@@ -50,17 +57,31 @@ impl PoseidonTable {
         }
     }
 
-    pub fn load(&self, region: &mut Region<'_, Fr>, hash_traces: &[([Fr; 2], Fr, Fr)]) {
+    /// Loads `hash_traces` into a table of `size` rows after checking that they fit and that
+    /// each hash is correct. Nothing is assigned if the check fails.
+    pub fn load(
+        &self,
+        region: &mut Region<'_, Fr>,
+        hash_traces: &[([Fr; 2], Fr, Fr)],
+        size: usize,
+    ) -> Result<(), PoseidonLoadError> {
+        check_hash_traces(hash_traces, size)?;
+        self.load_unchecked(region, hash_traces, size);
+        Ok(())
+    }
+
+    /// Loads `hash_traces` into the table without checking them, so an incorrect trace will
+    /// only show up as a failed lookup.
+    pub fn load_unchecked(
+        &self,
+        region: &mut Region<'_, Fr>,
+        hash_traces: &[([Fr; 2], Fr, Fr)],
+        size: usize,
+    ) {
         // The test poseidon table starts assigning from the first row, which has a disabled
         // selector, but this is fine because the poseidon_lookup in the ConstraintBuilder
         // doesn't include the mpt circuit's selector column.
         for (offset, hash_trace) in hash_traces.iter().enumerate() {
-            assert!(
-                Hashable::hash_with_domain([hash_trace.0[0], hash_trace.0[1]], hash_trace.1)
-                    == hash_trace.2,
-                "{:?}",
-                (hash_trace.0, hash_trace.1, hash_trace.2)
-            );
             for (column, value) in [
                 (self.left, hash_trace.0[0]),
                 (self.right, hash_trace.0[1]),
@@ -76,12 +97,60 @@ impl PoseidonTable {
 
         // We need to do this so that the fixed columns in the tests will not depend on the
         // number of poseidon hashes that are looked up.
-        for offset in hash_traces.len()..MAX_POSEIDON_ROWS {
+        for offset in hash_traces.len()..size {
             self.q_enable.assign(region, offset, Fr::one());
         }
     }
 }
 
+#[cfg(any(test, feature = "bench"))]
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum PoseidonLoadError {
+    #[error("{got} hash traces do not fit in a poseidon table of {limit} rows")]
+    TooManyTraces { got: usize, limit: usize },
+    #[error(transparent)]
+    HashMismatch(Box<HashMismatch>),
+}
+
+#[cfg(any(test, feature = "bench"))]
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("hash of {left:?} and {right:?} in domain {domain:?} is {got:?}, not {expected:?}")]
+pub struct HashMismatch {
+    pub left: Fr,
+    pub right: Fr,
+    pub domain: Fr,
+    pub expected: Fr,
+    pub got: Fr,
+}
+
+/// Checks that `hash_traces` fit in a poseidon table of `size` rows and that each hash is
+/// correct, i.e. that `PoseidonTable::load` will succeed.
+#[cfg(any(test, feature = "bench"))]
+pub fn check_hash_traces(
+    hash_traces: &[([Fr; 2], Fr, Fr)],
+    size: usize,
+) -> Result<(), PoseidonLoadError> {
+    if hash_traces.len() > size {
+        return Err(PoseidonLoadError::TooManyTraces {
+            got: hash_traces.len(),
+            limit: size,
+        });
+    }
+    for ([left, right], domain, expected) in hash_traces.iter().copied() {
+        let got = Hashable::hash_with_domain([left, right], domain);
+        if got != expected {
+            return Err(PoseidonLoadError::HashMismatch(Box::new(HashMismatch {
+                left,
+                right,
+                domain,
+                expected,
+                got,
+            })));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(any(test, feature = "bench"))]
 impl PoseidonLookup for PoseidonTable {
     fn lookup_columns(&self) -> (FixedColumn, [AdviceColumn; 6]) {
@@ -98,3 +167,42 @@ impl PoseidonLookup for PoseidonTable {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{types::HashDomain, util::domain_hash};
+
+    #[test]
+    fn test_check_hash_traces() {
+        let (left, right) = (Fr::from(1), Fr::from(2));
+        let domain = Fr::from(HashDomain::Pair);
+        let hash = domain_hash(left, right, HashDomain::Pair);
+        assert_eq!(
+            check_hash_traces(&[([left, right], domain, hash)], 1),
+            Ok(())
+        );
+
+        assert_eq!(
+            check_hash_traces(&[([left, right], domain, hash + Fr::one())], 1),
+            Err(PoseidonLoadError::HashMismatch(Box::new(HashMismatch {
+                left,
+                right,
+                domain,
+                expected: hash + Fr::one(),
+                got: hash,
+            })))
+        );
+    }
+
+    #[test]
+    fn test_check_hash_traces_too_many() {
+        let (left, right) = (Fr::from(1), Fr::from(2));
+        let domain = Fr::from(HashDomain::Pair);
+        let hash = domain_hash(left, right, HashDomain::Pair);
+        assert_eq!(
+            check_hash_traces(&[([left, right], domain, hash); 3], 2),
+            Err(PoseidonLoadError::TooManyTraces { got: 3, limit: 2 })
+        );
+    }
+}
@@ -6,9 +6,21 @@ use halo2_proofs::{
 };
 
 #[derive(Clone, Copy, Debug)]
-pub struct RlcRandomness(pub Challenge);
+pub struct RlcRandomness(
+    pub Challenge,
+    // Fixed randomness, so that rlc values in tests don't depend on how the challenge is derived.
+    #[cfg(test)] Option<u64>,
+);
 
 impl RlcRandomness {
+    pub fn new(challenge: Challenge) -> Self {
+        Self(
+            challenge,
+            #[cfg(test)]
+            None,
+        )
+    }
+
     pub fn configure<F: FromUniformBytes<64> + Ord>(cs: &mut ConstraintSystem<F>) -> Self {
         // TODO: this is a hack so that we don't get a "'No Column<Advice> is
         // used in phase Phase(0) while allocating a new "Challenge usable after
@@ -16,14 +28,30 @@ impl RlcRandomness {
         // Maybe we can fix this by deferring column allocation until the build call?
         let _ = cs.advice_column();
 
-        Self(cs.challenge_usable_after(FirstPhase))
+        Self::new(cs.challenge_usable_after(FirstPhase))
+    }
+
+    #[cfg(test)]
+    pub fn fixed<F: FromUniformBytes<64> + Ord>(
+        cs: &mut ConstraintSystem<F>,
+        randomness: u64,
+    ) -> Self {
+        Self(Self::configure(cs).0, Some(randomness))
     }
 
     pub fn query<F: FromUniformBytes<64> + Ord>(&self) -> Query<F> {
+        #[cfg(test)]
+        if let Some(randomness) = self.1 {
+            return Query::from(randomness);
+        }
         Query::Challenge(self.0)
     }
 
     pub fn value<F: FromUniformBytes<64> + Ord>(&self, layouter: &impl Layouter<F>) -> Value<F> {
+        #[cfg(test)]
+        if let Some(randomness) = self.1 {
+            return Value::known(F::from(randomness));
+        }
         layouter.get_challenge(self.0)
     }
 }
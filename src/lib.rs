@@ -14,9 +14,10 @@ mod util;
 pub mod mpt;
 pub mod serde;
 
-pub use gadgets::mpt_update::hash_traces;
-pub use mpt::MptCircuitConfig;
+pub use gadgets::mpt_update::{hash_traces, union_hash_traces};
+pub use mpt::{MptCircuitConfig, MptLookup};
 pub use mpt_table::MPTProofType;
+pub use util::{combine_hi_lo, fr_to_balance, storage_key_hashes, HashTrace};
 
 #[cfg(feature = "bench")]
 pub use circuit::TestCircuit;
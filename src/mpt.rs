@@ -4,9 +4,9 @@ use crate::{
         byte_bit::ByteBitGadget,
         byte_representation::ByteRepresentationConfig,
         canonical_representation::CanonicalRepresentationConfig,
-        key_bit::KeyBitConfig,
+        key_bit::{KeyBitConfig, KeyBitLookup},
         mpt_update::{
-            byte_representations, key_bit_lookups, mpt_update_keys, MptUpdateConfig,
+            byte_representations, hash_traces, key_bit_lookups, mpt_update_keys, MptUpdateConfig,
             MptUpdateLookup,
         },
         poseidon::PoseidonLookup,
@@ -21,19 +21,67 @@ use halo2_proofs::{
     plonk::{Challenge, ConstraintSystem, Error, Expression, VirtualCells},
 };
 use itertools::Itertools;
-use std::time::Instant;
+use std::{collections::BTreeSet, ops::Range, time::Instant};
+
+/// A row of the mpt table, as seen by a circuit looking up into it. `storage_key`, `new_root`,
+/// and `old_root` are rlc'd with the evm word challenge, as are `new_value` and `old_value` for
+/// proof types whose values are words.
+pub struct MptLookup<F> {
+    pub proof_type: Expression<F>,
+    pub address: Expression<F>,
+    pub storage_key: Expression<F>,
+    pub new_root: Expression<F>,
+    pub old_root: Expression<F>,
+    pub new_value: Expression<F>,
+    pub old_value: Expression<F>,
+}
+
+impl<F: FromUniformBytes<64> + Ord> MptLookup<F> {
+    /// Input expressions in the column order of [`MptCircuitConfig::lookup_exprs`]. Each is
+    /// multiplied by `enabled`, so that disabled rows look up the all 0 row.
+    pub fn to_lookup_row(self, enabled: Expression<F>) -> [Expression<F>; 8] {
+        let row = [
+            self.address,
+            self.storage_key,
+            self.proof_type,
+            self.new_root,
+            self.old_root,
+            self.new_value,
+            self.old_value,
+        ]
+        .map(|expression| expression * enabled.clone());
+        std::iter::once(enabled)
+            .chain(row)
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap()
+    }
+}
+
+/// The width of the mpt circuit, not counting the poseidon table it looks up into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitStats {
+    pub advice_columns: usize,
+    pub fixed_columns: usize,
+    pub gates: usize,
+    pub lookups: usize,
+    /// Highest degree of the gates, i.e. excluding lookups.
+    pub max_degree: usize,
+}
 
 /// Config for MptCircuit
 #[derive(Clone)]
 pub struct MptCircuitConfig {
-    selector: SelectorColumn,
+    pub(crate) selector: SelectorColumn,
     is_final_row: SelectorColumn,
-    rlc_randomness: RlcRandomness,
-    mpt_update: MptUpdateConfig,
+    pub(crate) rlc_randomness: RlcRandomness,
+    pub(crate) mpt_update: MptUpdateConfig,
     canonical_representation: CanonicalRepresentationConfig,
     key_bit: KeyBitConfig,
     byte_bit: ByteBitGadget,
     byte_representation: ByteRepresentationConfig,
+    stats: CircuitStats,
+    unused_columns: Vec<String>,
 }
 
 impl MptCircuitConfig {
@@ -42,8 +90,10 @@ impl MptCircuitConfig {
         evm_word_challenge: Challenge,
         poseidon: &impl PoseidonLookup,
     ) -> Self {
+        let (advice_columns, fixed_columns, gates, lookups) = ConstraintBuilder::column_counts(cs);
+
         let selector = SelectorColumn(cs.fixed_column());
-        let rlc_randomness = RlcRandomness(evm_word_challenge);
+        let rlc_randomness = RlcRandomness::new(evm_word_challenge);
         let mut cb = ConstraintBuilder::new(selector);
 
         let byte_bit = ByteBitGadget::configure(cs, &mut cb);
@@ -97,7 +147,18 @@ impl MptCircuitConfig {
             }
         });
 
+        let max_degree = cb.max_degree();
         cb.build(cs);
+        let (total_advice_columns, total_fixed_columns, total_gates, total_lookups) =
+            ConstraintBuilder::column_counts(cs);
+        let stats = CircuitStats {
+            advice_columns: total_advice_columns - advice_columns,
+            fixed_columns: total_fixed_columns - fixed_columns,
+            gates: total_gates - gates,
+            lookups: total_lookups - lookups,
+            max_degree,
+        };
+        let unused_columns = unused_advice_columns(cs, advice_columns..total_advice_columns);
 
         Self {
             selector,
@@ -108,9 +169,21 @@ impl MptCircuitConfig {
             byte_bit,
             canonical_representation,
             byte_representation,
+            stats,
+            unused_columns,
         }
     }
 
+    pub fn stats(&self) -> CircuitStats {
+        self.stats
+    }
+
+    /// Advice columns of the mpt circuit which are not queried by any of its gates or lookups, and
+    /// so could be removed.
+    pub fn unused_columns(&self) -> Vec<String> {
+        self.unused_columns.clone()
+    }
+
     pub fn assign(
         &self,
         layouter: &mut impl Layouter<Fr>,
@@ -123,11 +196,27 @@ impl MptCircuitConfig {
         let mpt_updates_assign_dur = Instant::now();
         let use_par = std::env::var("PARALLEL_SYN").map_or(true, |s| s == *"true");
         if use_par {
-            let n_assigned_rows = self.mpt_update.assign_par(layouter, proofs, randomness);
+            let n_assigned_rows = {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::info_span!(
+                    "mpt_update",
+                    rows = MptUpdateConfig::n_rows_required(proofs)
+                )
+                .entered();
+                self.mpt_update.assign_par(layouter, proofs, randomness)
+            };
 
             layouter.assign_region(
                 || "mpt update padding rows",
                 |mut region| {
+                    // Nothing has checked that the updates fit in n_rows yet, so this must not
+                    // underflow.
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::info_span!(
+                        "padding",
+                        rows = n_rows.saturating_sub(1 + n_assigned_rows)
+                    )
+                    .entered();
                     if n_assigned_rows == 0 {
                         // first row is all-zeroes row
                         for offset in 1..n_rows {
@@ -145,7 +234,15 @@ impl MptCircuitConfig {
             layouter.assign_region(
                 || "mpt update",
                 |mut region| {
-                    let n_assigned_rows = self.mpt_update.assign(&mut region, proofs, randomness);
+                    let n_assigned_rows = {
+                        #[cfg(feature = "tracing")]
+                        let _span = tracing::info_span!(
+                            "mpt_update",
+                            rows = MptUpdateConfig::n_rows_required(proofs)
+                        )
+                        .entered();
+                        self.mpt_update.assign(&mut region, proofs, randomness)
+                    };
 
                     assert!(
                         2 + n_assigned_rows <= n_rows,
@@ -153,6 +250,9 @@ impl MptCircuitConfig {
                     all-zero row + at least 1 final padding row. Only {n_rows} rows available."
                     );
 
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::info_span!("padding", rows = n_rows - 1 - n_assigned_rows)
+                        .entered();
                     for offset in (1 + n_assigned_rows)..n_rows {
                         self.mpt_update.assign_padding_row(&mut region, offset);
                     }
@@ -170,7 +270,10 @@ impl MptCircuitConfig {
         if use_par {
             let key_bit_time = {
                 let dur = Instant::now();
-                self.key_bit.assign_par(layouter, &key_bit_lookups(proofs));
+                let lookups = key_bit_lookups(proofs);
+                #[cfg(feature = "tracing")]
+                let _span = tracing::info_span!("key_bit", rows = lookups.len()).entered();
+                self.key_bit.assign_par(layouter, &lookups);
                 dur.elapsed()
             };
             log::debug!("mpt key_bit assignment took {:?}", key_bit_time);
@@ -197,6 +300,9 @@ impl MptCircuitConfig {
         if use_par {
             let canon_repr_time = {
                 let dur = Instant::now();
+                #[cfg(feature = "tracing")]
+                let _span = tracing::info_span!("canonical_representation", rows = 32 * keys.len())
+                    .entered();
                 self.canonical_representation
                     .assign_par(layouter, randomness, &keys, n_rows);
                 dur.elapsed()
@@ -213,9 +319,22 @@ impl MptCircuitConfig {
 
                 let keys_assign_dur = Instant::now();
                 if !use_par {
-                    self.canonical_representation
-                        .assign(&mut region, randomness, &keys, n_rows);
-                    self.key_bit.assign(&mut region, &key_bit_lookups(proofs));
+                    {
+                        #[cfg(feature = "tracing")]
+                        let _span =
+                            tracing::info_span!("canonical_representation", rows = 32 * keys.len())
+                                .entered();
+                        self.canonical_representation.assign(
+                            &mut region,
+                            randomness,
+                            &keys,
+                            n_rows,
+                        );
+                    }
+                    let lookups = key_bit_lookups(proofs);
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::info_span!("key_bit", rows = lookups.len()).entered();
+                    self.key_bit.assign(&mut region, &lookups);
                 }
 
                 let byte_bit_time = {
@@ -225,6 +344,13 @@ impl MptCircuitConfig {
                 };
                 let byte_repr_time = {
                     let dur = Instant::now();
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::info_span!(
+                        "byte_representation",
+                        rows =
+                            ByteRepresentationConfig::n_rows_required(&u32s, &u64s, &u128s, &frs)
+                    )
+                    .entered();
                     self.byte_representation.assign(
                         &mut region,
                         &u32s,
@@ -253,6 +379,9 @@ impl MptCircuitConfig {
         )
     }
 
+    /// Expressions for the columns of the mpt table, in the order
+    /// `[selector, address, storage_key_rlc, proof_type, new_root_rlc, old_root_rlc, new_value,
+    /// old_value]`. All but the selector are 0 on rows which do not start an mpt update.
     pub fn lookup_exprs<F: FromUniformBytes<64> + Ord>(
         &self,
         meta: &mut VirtualCells<'_, F>,
@@ -265,6 +394,37 @@ impl MptCircuitConfig {
             .unwrap()
     }
 
+    /// Adds a lookup of `row` into the mpt table. `row` returns the condition under which the
+    /// lookup is enabled along with the values to look up.
+    pub fn lookup<F: FromUniformBytes<64> + Ord>(
+        &self,
+        cs: &mut ConstraintSystem<F>,
+        name: &'static str,
+        row: impl FnOnce(&mut VirtualCells<'_, F>) -> (Expression<F>, MptLookup<F>),
+    ) {
+        cs.lookup_any(name, |meta| {
+            let (enabled, row) = row(meta);
+            row.to_lookup_row(enabled)
+                .into_iter()
+                .zip_eq(self.lookup_exprs(meta))
+                .collect()
+        });
+    }
+
+    /// Constrains `bit` to be bit `index` of `key` with a lookup into the key bit table of the mpt
+    /// circuit, which lets a host circuit share the table. The table only has the bits of the keys
+    /// in the mpt updates that were assigned. The every row selector of `cb` must be enabled on
+    /// all rows of the mpt circuit, since the lookup requires it on the matching table row.
+    pub fn lookup_key_bit(
+        &self,
+        cb: &mut ConstraintBuilder<Fr>,
+        key: Query<Fr>,
+        index: Query<Fr>,
+        bit: Query<Fr>,
+    ) {
+        self.key_bit.lookup_at(cb, &self.byte_bit, key, index, bit);
+    }
+
     /// The number of minimum number of rows required for the mpt circuit.
     pub fn n_rows_required(proofs: &[Proof]) -> usize {
         let (u32s, u64s, u128s, frs) = byte_representations(proofs);
@@ -272,7 +432,10 @@ impl MptCircuitConfig {
         // +1 for the final padding row to satisfy the "final mpt update is padding" constraint.
         1 + *[
             MptUpdateConfig::n_rows_required(proofs),
-            CanonicalRepresentationConfig::n_rows_required(&mpt_update_keys(proofs)),
+            // assign pads the canonical representations up to n_rows / 32 - 1 keys, which must
+            // be at least the number of keys. This also covers
+            // CanonicalRepresentationConfig::n_rows_required.
+            32 * (mpt_update_keys(proofs).len() + 1),
             KeyBitConfig::n_rows_required(&key_bit_lookups(proofs)),
             // TODO: move rlc lookup for frs into CanonicalRepresentationConfig.
             ByteRepresentationConfig::n_rows_required(&u32s, &u64s, &u128s, &frs),
@@ -282,4 +445,46 @@ impl MptCircuitConfig {
         .max()
         .unwrap()
     }
+
+    /// The smallest k such that 2^k rows fit both the rows required for `proofs` and the rows of
+    /// the poseidon table they look up into, plus `n_unusable_rows` rows reserved by the proving
+    /// system at the end of the circuit.
+    pub fn k_required(proofs: &[Proof], n_unusable_rows: usize) -> u32 {
+        let n_rows = std::cmp::max(Self::n_rows_required(proofs), hash_traces(proofs).len());
+        (n_rows + n_unusable_rows)
+            .next_power_of_two()
+            .trailing_zeros()
+    }
+}
+
+fn unused_advice_columns(cs: &ConstraintSystem<Fr>, columns: Range<usize>) -> Vec<String> {
+    let mut queried = BTreeSet::new();
+    let gate_expressions = cs.gates().iter().flat_map(|gate| gate.polynomials());
+    let lookup_expressions = cs.lookups().iter().flat_map(|lookup| {
+        lookup
+            .input_expressions()
+            .iter()
+            .chain(lookup.table_expressions())
+    });
+    for expression in gate_expressions.chain(lookup_expressions) {
+        queried_advice_columns(expression, &mut queried);
+    }
+    columns
+        .filter(|index| !queried.contains(index))
+        .map(|index| format!("advice column {index}"))
+        .collect()
+}
+
+fn queried_advice_columns(expression: &Expression<Fr>, queried: &mut BTreeSet<usize>) {
+    match expression {
+        Expression::Advice(query) => {
+            queried.insert(query.column_index());
+        }
+        Expression::Negated(a) | Expression::Scaled(a, _) => queried_advice_columns(a, queried),
+        Expression::Sum(a, b) | Expression::Product(a, b) => {
+            queried_advice_columns(a, queried);
+            queried_advice_columns(b, queried);
+        }
+        _ => {}
+    }
 }
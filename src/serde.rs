@@ -1,12 +1,19 @@
 //! deserialize data for operations
 //!
+use crate::util::try_fr;
 use num_bigint::BigUint;
 use serde::{
-    de::{Deserializer, Error},
+    de::{DeserializeOwned, Deserializer, Error},
     ser::Serializer,
     Deserialize, Serialize,
 };
-use std::fmt::{Debug, Display, Formatter};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{Debug, Display, Formatter},
+    io::Read,
+    marker::PhantomData,
+};
 
 /// Indicate the operation type of a row in MPT circuit
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -25,12 +32,34 @@ pub enum HashType {
     Leaf,
 }
 
+impl HashType {
+    /// leaf node
+    pub fn is_leaf(&self) -> bool {
+        *self == HashType::Leaf
+    }
+
+    /// node which ends a path, i.e. a leaf or an empty node
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, HashType::Leaf | HashType::Empty)
+    }
+
+    /// leaf node which is extended to middle in insert
+    pub fn is_leaf_extension(&self) -> bool {
+        matches!(self, HashType::LeafExt | HashType::LeafExtFinal)
+    }
+
+    /// middle node
+    pub fn is_branch(&self) -> bool {
+        *self == HashType::Middle
+    }
+}
+
 impl<'de> Deserialize<'de> for HashType {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        match <&'de str>::deserialize(deserializer)? {
+        match Cow::<'de, str>::deserialize(deserializer)?.as_ref() {
             "empty" => Ok(HashType::Empty),
             "middle" => Ok(HashType::Middle),
             "leafExt" => Ok(HashType::LeafExt),
@@ -59,9 +88,10 @@ impl<'de, const LEN: usize> Deserialize<'de> for HexBytes<LEN> {
     where
         D: Deserializer<'de>,
     {
-        let de_str = <&'de str>::deserialize(deserializer)?;
+        // not a &str, so that traces can also be read from an io::Read
+        let de_str = Cow::<'de, str>::deserialize(deserializer)?;
 
-        de_str.try_into().map_err(D::Error::custom)
+        de_str.as_ref().try_into().map_err(D::Error::custom)
     }
 }
 
@@ -69,7 +99,7 @@ fn de_uint_bin<'de, D>(deserializer: D) -> Result<BigUint, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let de_str = <&'de str>::deserialize(deserializer)?;
+    let de_str = Cow::<'de, str>::deserialize(deserializer)?;
     BigUint::parse_bytes(de_str.as_bytes(), 2).ok_or_else(|| D::Error::custom(RowDeError::BigInt))
 }
 
@@ -93,7 +123,7 @@ fn de_uint_hex<'de, D>(deserializer: D) -> Result<BigUint, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let de_str = <&'de str>::deserialize(deserializer)?;
+    let de_str = Cow::<'de, str>::deserialize(deserializer)?;
     // handling "0x" prefix and a special case that only "0x" occur (i.e.: 0)
     let ret = if de_str.starts_with("0x") {
         if de_str.len() == 2 {
@@ -116,6 +146,17 @@ pub enum RowDeError {
     #[error("cannot parse bigInt repr")]
     /// bigInt decode error
     BigInt,
+    #[error("expected {expected} hex encoded bytes, got {actual}")]
+    /// hex string decodes to the wrong number of bytes
+    HexLength {
+        /// number of bytes expected
+        expected: usize,
+        /// number of bytes in the hex string
+        actual: usize,
+    },
+    #[error("{0} is not a canonical field element")]
+    /// hash is not less than the field modulus
+    NonCanonicalField(Hash),
 }
 
 #[derive(Debug, Deserialize)]
@@ -236,16 +277,19 @@ impl<const LEN: usize> AsMut<[u8; LEN]> for HexBytes<LEN> {
 }
 
 impl<const LEN: usize> TryFrom<&str> for HexBytes<LEN> {
-    type Error = hex::FromHexError;
+    type Error = RowDeError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let mut bytes = Self::default();
         // handling "0x" prefix
-        if value.starts_with("0x") {
-            hex::decode_to_slice(value.get(2..).unwrap(), &mut bytes.0)?;
-        } else {
-            hex::decode_to_slice(value, &mut bytes.0)?;
+        let value = value.strip_prefix("0x").unwrap_or(value);
+        if value.len() != 2 * LEN {
+            return Err(RowDeError::HexLength {
+                expected: LEN,
+                actual: value.len().div_ceil(2),
+            });
         }
+        hex::decode_to_slice(value, &mut bytes.0)?;
 
         Ok(bytes)
     }
@@ -348,3 +392,164 @@ pub struct SMTTrace {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub state_update: Option<[Option<StateData>; 2]>,
 }
+
+impl SMTPath {
+    fn hashes(&self) -> impl Iterator<Item = &Hash> {
+        std::iter::once(&self.root).chain(
+            self.leaf
+                .iter()
+                .chain(&self.path)
+                .flat_map(|node| [&node.value, &node.sibling]),
+        )
+    }
+}
+
+impl SMTTrace {
+    /// check that every hash in the trace is a canonical field element, so that converting the
+    /// trace into a `Proof` cannot panic on it
+    pub fn check_field_elements(&self) -> Result<(), RowDeError> {
+        self.account_path
+            .iter()
+            .chain(self.state_path.iter().flatten())
+            .flat_map(SMTPath::hashes)
+            .chain([&self.account_key])
+            .chain(&self.common_state_root)
+            .chain(&self.state_key)
+            .try_for_each(|hash| try_fr(*hash).map(|_| ()))
+    }
+}
+
+/// group traces by account address, keeping the position of each trace in the input so
+/// the original order can be restored with `merge_shards`
+pub fn shard_by_account(traces: Vec<SMTTrace>) -> HashMap<Address, Vec<(usize, SMTTrace)>> {
+    let mut shards: HashMap<Address, Vec<(usize, SMTTrace)>> = HashMap::new();
+    for (index, trace) in traces.into_iter().enumerate() {
+        shards
+            .entry(trace.address)
+            .or_default()
+            .push((index, trace));
+    }
+    shards
+}
+
+/// inverse of `shard_by_account`: flatten shards back into the original trace order
+pub fn merge_shards(shards: HashMap<Address, Vec<(usize, SMTTrace)>>) -> Vec<SMTTrace> {
+    let mut traces: Vec<_> = shards.into_values().flatten().collect();
+    traces.sort_by_key(|(index, _)| *index);
+    traces.into_iter().map(|(_, trace)| trace).collect()
+}
+
+/// iterator over a json array of SMTTraces (or of other values, such as `(MPTProofType, SMTTrace)`
+/// pairs), which deserializes one element at a time instead of loading the whole array into
+/// memory. The reader is read byte by byte, so it should be buffered.
+pub struct SMTTraceReader<R: Read, T = SMTTrace> {
+    reader: R,
+    started: bool,
+    done: bool,
+    _element: PhantomData<T>,
+}
+
+impl<R: Read, T: DeserializeOwned> SMTTraceReader<R, T> {
+    /// create a reader over a json array of traces
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            started: false,
+            done: false,
+            _element: PhantomData,
+        }
+    }
+
+    // the next byte which is not json whitespace
+    fn next_token(&mut self) -> Result<u8, serde_json::Error> {
+        for byte in (&mut self.reader).bytes() {
+            let byte = byte.map_err(serde_json::Error::io)?;
+            if !matches!(byte, b' ' | b'\n' | b'\r' | b'\t') {
+                return Ok(byte);
+            }
+        }
+        Err(serde_json::Error::custom("EOF while parsing a trace array"))
+    }
+
+    // strips the `[` or `,` before the next element, and returns the first byte of the element,
+    // or None if the array has ended.
+    fn next_element_start(&mut self) -> Result<Option<u8>, serde_json::Error> {
+        let token = self.next_token()?;
+        let token = if self.started {
+            match token {
+                b',' => self.next_token()?,
+                b']' => return Ok(None),
+                _ => {
+                    return Err(serde_json::Error::custom(
+                        "expected `,` or `]` in trace array",
+                    ))
+                }
+            }
+        } else {
+            self.started = true;
+            match token {
+                b'[' => match self.next_token()? {
+                    b']' => return Ok(None),
+                    token => token,
+                },
+                _ => return Err(serde_json::Error::custom("expected `[` at start of traces")),
+            }
+        };
+        Ok(Some(token))
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for SMTTraceReader<R, T> {
+    type Item = Result<T, serde_json::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let element = match self.next_element_start() {
+            Ok(Some(start)) => {
+                // an element is an object or array, so the deserializer stops at its closing
+                // bracket without reading past the element.
+                let start = [start];
+                let reader = start.as_slice().chain(&mut self.reader);
+                Some(T::deserialize(&mut serde_json::Deserializer::from_reader(
+                    reader,
+                )))
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        };
+        if !matches!(element, Some(Ok(_))) {
+            self.done = true;
+        }
+        element
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_type_predicates() {
+        use HashType::*;
+        // (hash type, is_leaf, is_terminal, is_leaf_extension, is_branch)
+        for (hash_type, leaf, terminal, leaf_extension, branch) in [
+            (Start, false, false, false, false),
+            (Empty, false, true, false, false),
+            (Middle, false, false, false, true),
+            (LeafExt, false, false, true, false),
+            (LeafExtFinal, false, false, true, false),
+            (Leaf, true, true, false, false),
+        ] {
+            assert_eq!(hash_type.is_leaf(), leaf, "{hash_type:?}");
+            assert_eq!(hash_type.is_terminal(), terminal, "{hash_type:?}");
+            assert_eq!(
+                hash_type.is_leaf_extension(),
+                leaf_extension,
+                "{hash_type:?}"
+            );
+            assert_eq!(hash_type.is_branch(), branch, "{hash_type:?}");
+        }
+    }
+}
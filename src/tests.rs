@@ -1,9 +1,25 @@
-use crate::{circuit::TestCircuit, serde::SMTTrace, types::Proof, MPTProofType, MptCircuitConfig};
+use crate::{
+    circuit::TestCircuit,
+    constraint_builder::{AdviceColumn, ConstraintBuilder, FixedColumn, Query, SelectorColumn},
+    gadgets::{
+        mpt_update::{
+            byte_representations, byte_representations_serial, key_bit_lookups, mpt_update_keys,
+            proof_hash_traces,
+        },
+        poseidon::PoseidonLoadError,
+    },
+    hash_traces,
+    mpt::CircuitStats,
+    serde::{merge_shards, shard_by_account, HexBytes, RowDeError, SMTTrace, SMTTraceReader},
+    types::{trie::TrieRow, HashDomain, Proof},
+    union_hash_traces, MPTProofType, MptCircuitConfig, MptLookup,
+};
 use ethers_core::types::{Address, U256};
 use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
     dev::MockProver,
     halo2curves::bn256::{Bn256, Fr},
-    plonk::{keygen_vk, Circuit, ConstraintSystem},
+    plonk::{keygen_vk, Circuit, ConstraintSystem, Error, Expression},
     poly::kzg::commitment::ParamsKZG,
 };
 use mpt_zktrie::state::{builder::HASH_SCHEME_DONE, witness::WitnessGenerator, ZktrieState};
@@ -59,6 +75,27 @@ fn mock_prove(witness: Vec<(MPTProofType, SMTTrace)>) {
     assert_eq!(prover.verify(), Ok(()),);
 }
 
+fn verify_failures(circuit: &impl Circuit<Fr>) -> Vec<String> {
+    let prover = MockProver::<Fr>::run(14, circuit, vec![]).unwrap();
+    prover
+        .verify()
+        .err()
+        .unwrap_or_default()
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+// Checks that the circuit fails to verify, and that `constraint` is one of the failures.
+fn assert_constraint_not_satisfied(circuit: &impl Circuit<Fr>, constraint: &str) {
+    let failures = verify_failures(circuit);
+    assert!(
+        failures.iter().any(|failure| failure.contains(constraint)),
+        "\"{constraint}\" not among {} failures",
+        failures.len()
+    );
+}
+
 #[test]
 fn degree() {
     let mut meta = ConstraintSystem::<Fr>::default();
@@ -66,6 +103,39 @@ fn degree() {
     assert_eq!(meta.degree(), 9);
 }
 
+#[test]
+fn circuit_stats() {
+    let mut meta = ConstraintSystem::<Fr>::default();
+    let (_, config) = TestCircuit::configure(&mut meta);
+    let stats = config.stats();
+
+    // Update these if a change is meant to alter the shape of the circuit.
+    assert_eq!(
+        stats,
+        CircuitStats {
+            advice_columns: 63,
+            fixed_columns: 10,
+            gates: 299,
+            lookups: 76,
+            max_degree: 8,
+        }
+    );
+
+    // The poseidon table has 6 advice columns and 1 fixed column.
+    assert_eq!(stats.advice_columns + 6, meta.num_advice_columns());
+    assert_eq!(stats.fixed_columns + 1, meta.num_fixed_columns());
+    assert_eq!(stats.gates, meta.gates().len());
+    assert_eq!(stats.lookups, meta.lookups().len());
+    assert!(stats.max_degree <= meta.degree());
+}
+
+#[test]
+fn no_unused_columns() {
+    let mut cs = ConstraintSystem::<Fr>::default();
+    let (_, mpt_config) = TestCircuit::configure(&mut cs);
+    assert_eq!(mpt_config.unused_columns(), Vec::<String>::new());
+}
+
 #[test]
 fn verifying_key_constant() {
     let params = ParamsKZG::<Bn256>::setup(17, rand_chacha::ChaCha20Rng::seed_from_u64(2));
@@ -99,6 +169,17 @@ fn all_padding() {
     mock_prove(vec![]);
 }
 
+#[test]
+fn all_padding_small_circuit() {
+    // Padding rows alone must satisfy every gate, even in the smallest circuit. This can't be
+    // 64 rows: the byte tables take 8 * 256 + 1 rows, and the selector must be enabled on all of
+    // them for the range checks of the padding keys' canonical representations.
+    let n_rows = MptCircuitConfig::n_rows_required(&[]);
+    let circuit = TestCircuit::new(n_rows, vec![]);
+    let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+    assert_eq!(prover.verify(), Ok(()));
+}
+
 #[test]
 fn empty_account_type_1() {
     let mut generator = initial_generator();
@@ -1071,3 +1152,601 @@ fn verify_benchmark_trace() {
     let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
     assert_eq!(prover.verify(), Ok(()));
 }
+
+#[test]
+fn too_many_hash_traces_for_circuit() {
+    let witness: Vec<(MPTProofType, SMTTrace)> =
+        serde_json::from_str(include_str!("../benches/traces.json")).unwrap();
+    let proofs: Vec<_> = witness.into_iter().map(Proof::from).collect();
+    let n_hash_traces = hash_traces(&proofs).len();
+
+    assert_eq!(
+        TestCircuit::try_from_proofs(1000, proofs).unwrap_err(),
+        PoseidonLoadError::TooManyTraces {
+            got: n_hash_traces,
+            limit: 1000
+        }
+    );
+}
+
+#[test]
+fn union_hash_traces_covers_each_batch() {
+    let batches: Vec<Vec<Proof>> = [
+        (
+            MPTProofType::StorageChanged,
+            include_str!("traces/empty_storage_type_1_update_c.json"),
+        ),
+        (
+            MPTProofType::CodeHashExists,
+            include_str!("traces/existing_account_keccak_codehash_update.json"),
+        ),
+        (
+            MPTProofType::BalanceChanged,
+            include_str!("traces/empty_account_type_2_balance_update.json"),
+        ),
+    ]
+    .into_iter()
+    .map(|(proof_type, json)| {
+        let trace: SMTTrace = serde_json::from_str(json).unwrap();
+        vec![Proof::from((proof_type, trace))]
+    })
+    .collect();
+
+    let union = union_hash_traces(&batches);
+    assert!(union.windows(2).all(|pair| pair[0] < pair[1]));
+    for proofs in &batches {
+        for trace in hash_traces(proofs) {
+            assert!(union.binary_search(&trace).is_ok());
+        }
+    }
+}
+
+#[test]
+fn proof_hash_traces_bound_hash_traces() {
+    let witness: Vec<(MPTProofType, SMTTrace)> =
+        serde_json::from_str(include_str!("../benches/traces.json")).unwrap();
+    let proofs: Vec<_> = witness.into_iter().map(Proof::from).collect();
+
+    let per_proof: Vec<_> = proofs.iter().map(proof_hash_traces).collect();
+    let n_per_proof: usize = per_proof.iter().map(Vec::len).sum();
+    let deduped = hash_traces(&proofs);
+    // hash_traces also includes the hash of the empty pair.
+    assert!(deduped.len() <= 1 + n_per_proof);
+    for trace in per_proof.iter().flatten() {
+        assert!(deduped.binary_search(trace).is_ok());
+    }
+}
+
+#[test]
+fn shard_by_account_then_merge_preserves_order() {
+    let witness: Vec<(MPTProofType, SMTTrace)> =
+        serde_json::from_str(include_str!("../benches/traces.json")).unwrap();
+    let traces: Vec<_> = witness.into_iter().map(|(_, trace)| trace).collect();
+
+    let shards = shard_by_account(traces.clone());
+    for (address, shard) in &shards {
+        assert!(shard.iter().all(|(_, trace)| trace.address == *address));
+        assert!(shard.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    let merged = merge_shards(shards);
+    assert_eq!(
+        serde_json::to_string(&merged).unwrap(),
+        serde_json::to_string(&traces).unwrap()
+    );
+}
+
+#[test]
+fn smt_trace_reader_matches_batch_loaded_traces() {
+    let json = include_str!("../benches/traces.json");
+    let witness: Vec<(MPTProofType, SMTTrace)> = serde_json::from_str(json).unwrap();
+
+    let streamed: Vec<(MPTProofType, SMTTrace)> = SMTTraceReader::new(std::io::Cursor::new(json))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(streamed.len(), witness.len());
+    for ((streamed_type, streamed_trace), (proof_type, trace)) in streamed.iter().zip(&witness) {
+        assert_eq!(*streamed_type as u64, *proof_type as u64);
+        assert_eq!(
+            serde_json::to_string(streamed_trace).unwrap(),
+            serde_json::to_string(trace).unwrap()
+        );
+    }
+
+    let empty: Vec<SMTTrace> = SMTTraceReader::new(std::io::Cursor::new(" [ ] "))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(empty.is_empty());
+    assert!(
+        SMTTraceReader::<_, SMTTrace>::new(std::io::Cursor::new("[{}"))
+            .next()
+            .unwrap()
+            .is_err()
+    );
+}
+
+#[test]
+fn n_rows_required_fits_canonical_representations() {
+    let witness: Vec<(MPTProofType, SMTTrace)> =
+        serde_json::from_str(include_str!("../benches/traces.json")).unwrap();
+    let proofs: Vec<_> = witness.into_iter().map(Proof::from).collect();
+
+    let n_rows_required = MptCircuitConfig::n_rows_required(&proofs);
+    assert!(n_rows_required / 32 > mpt_update_keys(&proofs).len());
+}
+
+#[test]
+fn k_required() {
+    let witness: Vec<(MPTProofType, SMTTrace)> = vec![(
+        MPTProofType::BalanceChanged,
+        serde_json::from_str(include_str!("traces/existing_account_balance_update.json")).unwrap(),
+    )];
+    let proofs: Vec<_> = witness.clone().into_iter().map(Proof::from).collect();
+
+    let n_rows_required = MptCircuitConfig::n_rows_required(&proofs);
+    let k = MptCircuitConfig::k_required(&proofs, 64);
+    assert!(1 << (k - 1) < n_rows_required + 64);
+
+    let circuit = TestCircuit::new(n_rows_required, witness);
+    let prover = MockProver::<Fr>::run(k, &circuit, vec![]).unwrap();
+    assert_eq!(prover.verify(), Ok(()));
+}
+
+#[test]
+fn k_required_covers_hash_traces() {
+    let trace: SMTTrace =
+        serde_json::from_str(include_str!("traces/existing_account_balance_update.json")).unwrap();
+    let mut proof = Proof::from((MPTProofType::BalanceChanged, trace));
+    // Only the hash traces depend on account_trie_rows, so extra rows with distinct old hashes
+    // add poseidon table rows without adding mpt rows.
+    let row = proof.account_trie_rows.0[0].clone();
+    proof.account_trie_rows.0 = (0..5000u64)
+        .map(|i| TrieRow {
+            old: Fr::from(i),
+            ..row.clone()
+        })
+        .collect();
+    let proofs = vec![proof];
+
+    let n_hash_rows = hash_traces(&proofs).len();
+    assert!(n_hash_rows > MptCircuitConfig::n_rows_required(&proofs));
+
+    let k = MptCircuitConfig::k_required(&proofs, 64);
+    assert!(1 << k >= n_hash_rows + 64);
+    assert!(1 << (k - 1) < n_hash_rows + 64);
+}
+
+#[test]
+fn byte_representations_match_serial_byte_representations() {
+    // With the rayon feature, byte_representations computes proofs in parallel, which must not
+    // change its output.
+    let witness: Vec<(MPTProofType, SMTTrace)> =
+        serde_json::from_str(include_str!("../benches/traces.json")).unwrap();
+    let proofs: Vec<_> = witness.into_iter().map(Proof::from).collect();
+
+    let (u32s, u64s, u128s, frs) = byte_representations(&proofs);
+    let (serial_u32s, serial_u64s, serial_u128s, serial_frs) = byte_representations_serial(&proofs);
+    assert_eq!(u32s, serial_u32s);
+    assert_eq!(u64s, serial_u64s);
+    assert_eq!(u128s, serial_u128s);
+    assert_eq!(frs, serial_frs);
+}
+
+#[test]
+fn hash_traces_match_per_proof_hash_traces() {
+    let witness: Vec<(MPTProofType, SMTTrace)> =
+        serde_json::from_str(include_str!("../benches/traces.json")).unwrap();
+    let proofs: Vec<_> = witness.into_iter().map(Proof::from).collect();
+
+    let mut expected = hash_traces(&[]);
+    expected.extend(proofs.iter().flat_map(proof_hash_traces));
+    expected.sort();
+    expected.dedup();
+
+    assert_eq!(hash_traces(&proofs), expected);
+}
+
+#[derive(Clone, Default)]
+struct MptLookupCircuit {
+    mpt: TestCircuit,
+    proof_type: u64,
+}
+
+impl Circuit<Fr> for MptLookupCircuit {
+    type Config = (
+        <TestCircuit as Circuit<Fr>>::Config,
+        SelectorColumn,
+        FixedColumn,
+    );
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let mpt_config = TestCircuit::configure(cs);
+        let enabled = SelectorColumn(cs.fixed_column());
+        let proof_type = FixedColumn(cs.fixed_column());
+        mpt_config.1.lookup(cs, "mpt lookup", |meta| {
+            let zero = || Expression::Constant(Fr::from(0));
+            (
+                Query::from(enabled.current()).run(meta),
+                MptLookup {
+                    proof_type: proof_type.current().run(meta),
+                    address: zero(),
+                    storage_key: zero(),
+                    new_root: zero(),
+                    old_root: zero(),
+                    new_value: zero(),
+                    old_value: zero(),
+                },
+            )
+        });
+        (mpt_config, enabled, proof_type)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        let (mpt_config, enabled, proof_type) = config;
+        self.mpt
+            .synthesize(mpt_config, layouter.namespace(|| "mpt"))?;
+        layouter.assign_region(
+            || "mpt lookup",
+            |mut region| {
+                enabled.enable(&mut region, 0);
+                proof_type.assign(&mut region, 0, self.proof_type);
+                Ok(())
+            },
+        )
+    }
+}
+
+#[test]
+fn mpt_lookup_finds_padding_row() {
+    // The final row of the mpt table is an AccountDoesNotExist proof for address 0 in the empty
+    // trie, so it is present even when there are no updates.
+    let circuit = MptLookupCircuit {
+        mpt: TestCircuit::new(N_ROWS, vec![]),
+        proof_type: MPTProofType::AccountDoesNotExist as u64,
+    };
+    let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+    assert_eq!(prover.verify(), Ok(()));
+
+    let circuit = MptLookupCircuit {
+        mpt: TestCircuit::new(N_ROWS, vec![]),
+        proof_type: MPTProofType::StorageChanged as u64,
+    };
+    let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn check_field_elements_rejects_non_canonical_hash() {
+    let trace: SMTTrace = serde_json::from_str(include_str!(
+        "traces/empty_account_type_1_balance_update.json"
+    ))
+    .unwrap();
+    assert!(trace.check_field_elements().is_ok());
+
+    let mut bad_trace = trace;
+    bad_trace.account_path[1].root = HexBytes([0xff; 32]);
+    assert!(matches!(
+        bad_trace.check_field_elements(),
+        Err(RowDeError::NonCanonicalField(hash)) if hash == HexBytes([0xff; 32])
+    ));
+}
+
+#[test]
+fn try_from_trace_rejects_non_canonical_hash() {
+    let trace: SMTTrace = serde_json::from_str(include_str!(
+        "traces/empty_account_type_1_balance_update.json"
+    ))
+    .unwrap();
+    assert!(Proof::try_from_trace(MPTProofType::BalanceChanged, trace.clone()).is_ok());
+
+    let mut bad_trace = trace;
+    bad_trace.account_path[0].path[0].sibling = HexBytes([0xff; 32]);
+    assert!(matches!(
+        Proof::try_from_trace(MPTProofType::BalanceChanged, bad_trace),
+        Err(RowDeError::NonCanonicalField(hash)) if hash == HexBytes([0xff; 32])
+    ));
+}
+
+#[test]
+fn hex_bytes_length_is_checked() {
+    let address = "0x0102030405060708090a0b0c0d0e0f1011121314";
+    assert_eq!(
+        HexBytes::<20>::try_from(address).unwrap(),
+        HexBytes(core::array::from_fn(|i| i as u8 + 1))
+    );
+
+    let too_long = format!("{address}15");
+    for (hex, actual) in [("0x01020304", 4), ("0x010", 2), (too_long.as_str(), 21)] {
+        let error = HexBytes::<20>::try_from(hex).unwrap_err();
+        assert!(matches!(
+            error,
+            RowDeError::HexLength { expected: 20, actual: n } if n == actual
+        ));
+        assert_eq!(
+            error.to_string(),
+            format!("expected 20 hex encoded bytes, got {actual}")
+        );
+    }
+
+    let error = serde_json::from_str::<HexBytes<32>>(&format!("\"{address}\"")).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("expected 32 hex encoded bytes, got 20"));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn assignment_phases_are_traced() {
+    use std::sync::{Arc, Mutex};
+    use tracing::{span, Event, Metadata, Subscriber};
+
+    // Records the names of all spans created.
+    struct SpanNames(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Subscriber for SpanNames {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            let mut names = self.0.lock().unwrap();
+            names.push(span.metadata().name());
+            span::Id::from_u64(names.len() as u64)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let witness = vec![(
+        MPTProofType::BalanceChanged,
+        serde_json::from_str(include_str!(
+            "traces/empty_account_type_1_balance_update.json"
+        ))
+        .unwrap(),
+    )];
+    let names = Arc::new(Mutex::new(vec![]));
+    tracing::subscriber::with_default(SpanNames(names.clone()), || mock_prove(witness));
+
+    let names = names.lock().unwrap();
+    for phase in [
+        "mpt_update",
+        "key_bit",
+        "canonical_representation",
+        "byte_representation",
+        "padding",
+        "hash_traces",
+        "proof_hash_traces",
+    ] {
+        assert!(names.contains(&phase), "no {phase} span recorded");
+    }
+}
+
+#[test]
+fn no_trie_rows_after_account_leaf() {
+    // A balance update ends with its AccountLeaf3 row. Giving it the storage proof of a storage
+    // update appends StorageTrie and StorageLeaf0 rows after the account leaf, within the same
+    // update.
+    let balance_trace: SMTTrace =
+        serde_json::from_str(include_str!("traces/existing_account_balance_update.json")).unwrap();
+    let storage_trace: SMTTrace =
+        serde_json::from_str(include_str!("traces/existing_storage_update.json")).unwrap();
+    let mut proof = Proof::from((MPTProofType::BalanceChanged, balance_trace));
+    proof.storage = Proof::from((MPTProofType::StorageChanged, storage_trace)).storage;
+
+    assert_constraint_not_satisfied(
+        &TestCircuit::from_proofs(N_ROWS, vec![proof]),
+        "transition for current segment -> next segment",
+    );
+}
+
+// Assigns only the mpt updates, starting at row `offset`, along with the poseidon table. The
+// rest of the mpt circuit is left unassigned, so only mpt update gates should be checked.
+#[derive(Clone, Default)]
+struct MptUpdatesAtCircuit {
+    proofs: Vec<Proof>,
+    offset: usize,
+}
+
+impl Circuit<Fr> for MptUpdatesAtCircuit {
+    type Config = <TestCircuit as Circuit<Fr>>::Config;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+        TestCircuit::configure(cs)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        let (poseidon, mpt_config) = config;
+        assign_mpt_updates_at(
+            &mpt_config,
+            &mut layouter,
+            &self.proofs,
+            self.offset,
+            N_ROWS,
+        )?;
+        layouter.assign_region(
+            || "load poseidon table",
+            |mut region| {
+                poseidon.load_unchecked(&mut region, &hash_traces(&self.proofs), N_ROWS);
+                Ok(())
+            },
+        )
+    }
+}
+
+// Assigns only the selector and the mpt updates, with the first update starting at `offset`
+// instead of 1. This lets tests build witnesses that `assign` never produces, such as one whose
+// first enabled row is in the middle of an update.
+fn assign_mpt_updates_at(
+    config: &MptCircuitConfig,
+    layouter: &mut impl Layouter<Fr>,
+    proofs: &[Proof],
+    offset: usize,
+    n_rows: usize,
+) -> Result<(), Error> {
+    let randomness = config.rlc_randomness.value(layouter);
+    layouter.assign_region(
+        || "mpt updates",
+        |mut region| {
+            for offset in 1..n_rows {
+                config.selector.enable(&mut region, offset);
+            }
+            let mut offset = offset;
+            for proof in proofs {
+                config
+                    .mpt_update
+                    .assign_single_proof(&mut region, proof, randomness, offset);
+                offset += proof.n_rows();
+            }
+            for offset in offset..n_rows {
+                config.mpt_update.assign_padding_row(&mut region, offset);
+            }
+            Ok(())
+        },
+    )
+}
+
+#[test]
+fn first_enabled_row_starts_an_update() {
+    let trace: SMTTrace =
+        serde_json::from_str(include_str!("traces/existing_account_balance_update.json")).unwrap();
+    let proofs = vec![Proof::from((MPTProofType::BalanceChanged, trace))];
+    let constraint = "first enabled row starts an update";
+
+    let circuit = MptUpdatesAtCircuit {
+        proofs: proofs.clone(),
+        offset: 1,
+    };
+    assert!(!verify_failures(&circuit)
+        .iter()
+        .any(|failure| failure.contains(constraint)));
+
+    // Starting the update on row 0, which is not enabled, makes the first enabled row an
+    // AccountTrie row in the middle of the update.
+    let circuit = MptUpdatesAtCircuit { proofs, offset: 0 };
+    assert_constraint_not_satisfied(&circuit, constraint);
+}
+
+#[test]
+fn account_trie_rows_cannot_have_leaf_domain() {
+    let trace: SMTTrace =
+        serde_json::from_str(include_str!("traces/existing_account_balance_update.json")).unwrap();
+    let proof = Proof::from((MPTProofType::BalanceChanged, trace));
+    let constraint = "domain in allowed set for segment type";
+
+    let circuit = MptUpdatesAtCircuit {
+        proofs: vec![proof.clone()],
+        offset: 1,
+    };
+    assert!(!verify_failures(&circuit)
+        .iter()
+        .any(|failure| failure.contains(constraint)));
+
+    // AccountTrie rows only allow the branch domains, so (AccountTrie, Leaf) is not in the set.
+    let mut proof = proof;
+    proof.account_trie_rows.0[0].domain = HashDomain::Leaf;
+    let circuit = MptUpdatesAtCircuit {
+        proofs: vec![proof],
+        offset: 1,
+    };
+    assert_constraint_not_satisfied(&circuit, constraint);
+}
+
+// Looks up bits of keys against the key bit table of the mpt circuit it contains.
+#[derive(Clone, Default)]
+struct KeyBitHostCircuit {
+    mpt: TestCircuit,
+    // (key, index, bit)
+    queries: Vec<(Fr, usize, bool)>,
+}
+
+impl Circuit<Fr> for KeyBitHostCircuit {
+    type Config = (
+        <TestCircuit as Circuit<Fr>>::Config,
+        SelectorColumn,
+        [AdviceColumn; 3],
+    );
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let (poseidon, mpt_config) = TestCircuit::configure(cs);
+
+        let selector = SelectorColumn(cs.fixed_column());
+        let mut cb = ConstraintBuilder::new(selector);
+        let [key, index, bit] = cb.advice_columns(cs);
+        mpt_config.lookup_key_bit(&mut cb, key.current(), index.current(), bit.current());
+        cb.build(cs);
+
+        ((poseidon, mpt_config), selector, [key, index, bit])
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        let (mpt_config, selector, [key, index, bit]) = config;
+        self.mpt
+            .synthesize(mpt_config, layouter.namespace(|| "mpt circuit"))?;
+        layouter.assign_region(
+            || "key bit queries",
+            |mut region| {
+                // The lookups require the selector on the rows of the key bit table they match.
+                for offset in 0..N_ROWS {
+                    selector.enable(&mut region, offset);
+                    let (value, i, b) = self.queries.get(offset).copied().unwrap_or_default();
+                    key.assign(&mut region, offset, value);
+                    index.assign(&mut region, offset, i as u64);
+                    bit.assign(&mut region, offset, b);
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+#[test]
+fn host_circuit_looks_up_mpt_key_bits() {
+    let trace: SMTTrace =
+        serde_json::from_str(include_str!("traces/existing_account_balance_update.json")).unwrap();
+    let proofs = vec![Proof::from((MPTProofType::BalanceChanged, trace))];
+    // Query the bits in a different order than the table has them.
+    let queries: Vec<_> = key_bit_lookups(&proofs).into_iter().rev().take(8).collect();
+    let circuit = KeyBitHostCircuit {
+        mpt: TestCircuit::from_proofs(N_ROWS, proofs),
+        queries: queries.clone(),
+    };
+    assert_eq!(verify_failures(&circuit), Vec::<String>::new());
+
+    let (key, index, bit) = *queries.last().unwrap();
+    let circuit = KeyBitHostCircuit {
+        queries: vec![(key, index, !bit)],
+        ..circuit
+    };
+    assert!(verify_failures(&circuit)
+        .iter()
+        .any(|failure| failure.contains("bit = key.bit(index)")));
+}
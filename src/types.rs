@@ -1,19 +1,15 @@
 use crate::{
     gadgets::mpt_update::PathType,
-    serde::{AccountData, HexBytes, SMTNode, SMTPath, SMTTrace},
+    serde::{AccountData, RowDeError, SMTNode, SMTPath, SMTTrace},
     util::{
-        account_key, check_domain_consistency, domain_hash, fr_from_biguint, rlc,
+        account_key, check_domain_consistency, domain_hash, fr, fr_from_biguint, rlc, split_word,
         u256_from_biguint, u256_from_hex, u256_to_big_endian,
     },
     MPTProofType,
 };
-use ethers_core::{
-    k256::elliptic_curve::PrimeField,
-    types::{Address, U256},
-};
+use ethers_core::types::{Address, U256};
 use halo2_proofs::halo2curves::bn256::Fr;
 use itertools::{EitherOrBoth, Itertools};
-use num_bigint::BigUint;
 use num_traits::identities::Zero;
 
 pub mod storage;
@@ -206,6 +202,14 @@ impl From<AccountData> for EthAccount {
 }
 
 impl Proof {
+    /// Fallible version of `Proof::from` for untrusted traces. Returns an error instead of
+    /// panicking if any hash in the trace is not a canonical field element. Traces whose hashes
+    /// are canonical but inconsistent, e.g. a path that does not hash to its root, still panic.
+    pub fn try_from_trace(proof_type: MPTProofType, trace: SMTTrace) -> Result<Self, RowDeError> {
+        trace.check_field_elements()?;
+        Ok(Self::from((proof_type, trace)))
+    }
+
     pub fn n_rows(&self) -> usize {
         if self.old_account.is_none() && self.new_account.is_none() {
             return 1 + self.address_hash_traces.len();
@@ -389,8 +393,8 @@ impl From<(&MPTProofType, &SMTTrace)> for ClaimKind {
                     assert_eq!(old.code_size, new.code_size);
                     assert_eq!(old.code_hash, new.code_hash);
                     ClaimKind::PoseidonCodeHash {
-                        old: Some(big_uint_to_fr(&old.poseidon_code_hash)),
-                        new: Some(big_uint_to_fr(&new.poseidon_code_hash)),
+                        old: Some(fr_from_biguint(&old.poseidon_code_hash)),
+                        new: Some(fr_from_biguint(&new.poseidon_code_hash)),
                     }
                 }
                 MPTProofType::AccountDoesNotExist
@@ -403,6 +407,8 @@ impl From<(&MPTProofType, &SMTTrace)> for ClaimKind {
     }
 }
 
+// Panics if the trace is malformed. Use `Proof::try_from_trace` for traces that may contain
+// non-canonical field elements.
 impl From<(MPTProofType, SMTTrace)> for Proof {
     fn from((proof, trace): (MPTProofType, SMTTrace)) -> Self {
         let claim = Claim::from((&proof, &trace));
@@ -506,20 +512,20 @@ fn leaf_hash(path: SMTPath) -> Fr {
 }
 
 fn account_hash_traces(address: Address, account: AccountData, storage_root: Fr) -> [[Fr; 3]; 6] {
-    let (codehash_hi, codehash_lo) = hi_lo(account.code_hash);
+    let (codehash_hi, codehash_lo) = split_word(u256_from_biguint(&account.code_hash));
     let h1 = domain_hash(codehash_hi, codehash_lo, HashDomain::Pair);
     let h2 = domain_hash(storage_root, h1, HashDomain::AccountFields);
 
     let nonce_and_codesize =
         Fr::from(account.nonce) + Fr::from(account.code_size) * Fr::from(1 << 32).square();
-    let balance = big_uint_to_fr(&account.balance);
+    let balance = fr_from_biguint(&account.balance);
     let h3 = domain_hash(nonce_and_codesize, balance, HashDomain::AccountFields);
 
     let h4 = domain_hash(h3, h2, HashDomain::AccountFields);
 
     let account_key = account_key(address);
 
-    let poseidon_codehash = big_uint_to_fr(&account.poseidon_code_hash);
+    let poseidon_codehash = fr_from_biguint(&account.poseidon_code_hash);
     let account_hash = domain_hash(h4, poseidon_codehash, HashDomain::AccountFields);
 
     let mut account_hash_traces = [[Fr::zero(); 3]; 6];
@@ -960,28 +966,6 @@ fn check_hash_traces_new(traces: &[(bool, HashDomain, Fr, Fr, Fr, bool, bool)])
     }
 }
 
-fn fr(x: HexBytes<32>) -> Fr {
-    Fr::from_bytes(&x.0).unwrap()
-}
-
-fn big_uint_to_fr(i: &BigUint) -> Fr {
-    i.to_u64_digits()
-        .iter()
-        .rev() // to_u64_digits has least significant digit is first
-        .fold(Fr::zero(), |a, b| {
-            a * Fr::from(1 << 32).square() + Fr::from(*b)
-        })
-}
-
-fn hi_lo(x: BigUint) -> (Fr, Fr) {
-    let mut u64_digits = x.to_u64_digits();
-    u64_digits.resize(4, 0);
-    (
-        Fr::from_u128((u128::from(u64_digits[3]) << 64) + u128::from(u64_digits[2])),
-        Fr::from_u128((u128::from(u64_digits[1]) << 64) + u128::from(u64_digits[0])),
-    )
-}
-
 pub trait Bit {
     fn bit(&self, i: usize) -> bool;
 }
@@ -1,4 +1,8 @@
-use crate::{constraint_builder::Query, serde::HexBytes, types::HashDomain};
+use crate::{
+    constraint_builder::Query,
+    serde::{HexBytes, RowDeError},
+    types::HashDomain,
+};
 use ethers_core::types::{Address, U256};
 use halo2_proofs::{
     arithmetic::Field,
@@ -7,8 +11,13 @@ use halo2_proofs::{
 use hash_circuit::hash::Hashable;
 use num_bigint::BigUint;
 
+/// For hashes already known to be canonical, e.g. after `SMTTrace::check_field_elements`.
 pub(crate) fn fr(x: HexBytes<32>) -> Fr {
-    Fr::from_bytes(&x.0).unwrap()
+    try_fr(x).unwrap()
+}
+
+pub(crate) fn try_fr(x: HexBytes<32>) -> Result<Fr, RowDeError> {
+    Option::from(Fr::from_bytes(&x.0)).ok_or(RowDeError::NonCanonicalField(x))
 }
 
 pub fn domain_hash(x: Fr, y: Fr, domain: HashDomain) -> Fr {
@@ -17,6 +26,15 @@ pub fn domain_hash(x: Fr, y: Fr, domain: HashDomain) -> Fr {
 
 pub(crate) trait Bit {
     fn bit(&self, i: usize) -> bool;
+
+    /// The `len` bits starting at bit `start`, with bit `start` as the least significant bit of
+    /// the result. Bits past the end of the 256 bit representation are 0.
+    fn bits(&self, start: usize, len: usize) -> u64 {
+        assert!(len <= 64, "can only extract up to 64 bits");
+        (start..(start + len).min(256))
+            .rev()
+            .fold(0, |acc, i| acc << 1 | u64::from(self.bit(i)))
+    }
 }
 
 impl Bit for Fr {
@@ -34,20 +52,21 @@ pub(crate) fn u256_from_hex(x: HexBytes<32>) -> U256 {
 }
 
 pub(crate) fn split_word(x: U256) -> (Fr, Fr) {
-    let mut bytes = [0; 32];
-    x.to_big_endian(&mut bytes);
-    let high_bytes: [u8; 16] = bytes[..16].try_into().unwrap();
-    let low_bytes: [u8; 16] = bytes[16..].try_into().unwrap();
-
-    let high = Fr::from_u128(u128::from_be_bytes(high_bytes));
-    let low = Fr::from_u128(u128::from_be_bytes(low_bytes));
-    (high, low)
+    let (high, low) = u256_hi_lo(&x);
+    (Fr::from_u128(high), Fr::from_u128(low))
+}
 
-    // TODO: what's wrong with this?
-    // let [limb_0, limb_1, limb_2, limb_3] = key.0;
-    // let key_high = Fr::from_u128(u128::from(limb_2) + u128::from(limb_3) << 64);
-    // let key_low = Fr::from_u128(u128::from(limb_0) + u128::from(limb_1) << 64);
-    // hash(key_high, key_low)
+/// Inverse of `split_word`. Panics if either half is not less than 2^128.
+pub fn combine_hi_lo(high: Fr, low: Fr) -> U256 {
+    let [high, low] = [high, low].map(|x| {
+        let repr = x.to_repr();
+        assert!(
+            repr[16..].iter().all(|byte| *byte == 0),
+            "{x:?} does not fit in 128 bits"
+        );
+        u128::from_le_bytes(repr[..16].try_into().unwrap())
+    });
+    (U256::from(high) << 128) + U256::from(low)
 }
 
 pub(crate) fn u256_hi_lo(x: &U256) -> (u128, u128) {
@@ -66,6 +85,13 @@ pub(crate) fn fr_from_biguint(b: &BigUint) -> Fr {
         })
 }
 
+/// Recovers a balance from the field element `fr_from_biguint` folds its 64 bit limbs into. This
+/// is only well-defined for balances in the 128 bit range, since the folding of larger values can
+/// wrap around the field modulus.
+pub fn fr_to_balance(x: Fr) -> BigUint {
+    BigUint::from_bytes_le(x.to_repr().as_ref())
+}
+
 pub fn rlc(be_bytes: &[u8], randomness: Fr) -> Fr {
     let x = be_bytes.iter().fold(Fr::zero(), |acc, byte| {
         randomness * acc + Fr::from(u64::from(*byte))
@@ -88,6 +114,20 @@ pub fn storage_key_hash(key: U256) -> Fr {
     domain_hash(high, low, HashDomain::Pair)
 }
 
+/// Inputs, domain, and output of a poseidon hash, as loaded into the poseidon table.
+pub type HashTrace = ([Fr; 2], Fr, Fr);
+
+/// Hashes of `keys`, along with the hash traces needed to load them into the poseidon table.
+pub fn storage_key_hashes(keys: &[U256]) -> (Vec<Fr>, Vec<HashTrace>) {
+    keys.iter()
+        .map(|key| {
+            let (high, low) = split_word(*key);
+            let hash = domain_hash(high, low, HashDomain::Pair);
+            (hash, ([high, low], Fr::from(HashDomain::Pair), hash))
+        })
+        .unzip()
+}
+
 pub fn account_key(address: Address) -> Fr {
     let high_bytes: [u8; 16] = address.0[..16].try_into().unwrap();
     let low_bytes: [u8; 4] = address.0[16..].try_into().unwrap();
@@ -147,9 +187,83 @@ pub fn lagrange_polynomial<F: FromUniformBytes<64> + Ord>(
 #[cfg(test)]
 mod test {
     use super::*;
+    use proptest::prelude::*;
+    use rand_chacha::rand_core::{RngCore, SeedableRng};
 
     #[test]
     fn test_u256_hi_lo() {
         assert_eq!(u256_hi_lo(&U256::one()), (0, 1));
     }
+
+    #[test]
+    fn test_bits() {
+        let x = Fr::from(0b1011_0110_1101);
+        assert_eq!(x.bits(0, 4), 0b1101);
+        assert_eq!(x.bits(4, 5), 0b10110);
+        assert_eq!(x.bits(0, 64), 0b1011_0110_1101);
+        assert_eq!(x.bits(250, 10), 0);
+        assert_eq!(Fr::from(1).bits(0, 0), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn test_fr_to_balance(balance in any::<u128>()) {
+            let balance = BigUint::from(balance);
+            prop_assert_eq!(fr_to_balance(fr_from_biguint(&balance)), balance);
+        }
+    }
+
+    #[test]
+    fn test_storage_key_hashes() {
+        let keys = [U256::zero(), U256::one(), U256::MAX, U256::one() << 200];
+        let (hashes, hash_traces) = storage_key_hashes(&keys);
+        assert_eq!(hashes.len(), keys.len());
+        for (key, hash) in keys.into_iter().zip(hashes) {
+            assert_eq!(hash, storage_key_hash(key));
+        }
+        for ([left, right], domain, hash) in hash_traces {
+            assert_eq!(Hashable::hash_with_domain([left, right], domain), hash);
+        }
+    }
+
+    #[test]
+    fn test_split_word() {
+        let split_word_bytes = |x: U256| {
+            let mut bytes = [0; 32];
+            x.to_big_endian(&mut bytes);
+            let high_bytes: [u8; 16] = bytes[..16].try_into().unwrap();
+            let low_bytes: [u8; 16] = bytes[16..].try_into().unwrap();
+            (
+                Fr::from_u128(u128::from_be_bytes(high_bytes)),
+                Fr::from_u128(u128::from_be_bytes(low_bytes)),
+            )
+        };
+
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mut bytes = [0; 32];
+            rng.fill_bytes(&mut bytes);
+            let x = U256::from_big_endian(&bytes);
+            assert_eq!(split_word(x), split_word_bytes(x));
+        }
+        for x in [U256::zero(), U256::one(), U256::MAX, U256::one() << 128] {
+            assert_eq!(split_word(x), split_word_bytes(x));
+        }
+    }
+
+    #[test]
+    fn test_combine_hi_lo() {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(1);
+        for _ in 0..100 {
+            let mut bytes = [0; 32];
+            rng.fill_bytes(&mut bytes);
+            let x = U256::from_big_endian(&bytes);
+            let (high, low) = split_word(x);
+            assert_eq!(combine_hi_lo(high, low), x);
+        }
+        for x in [U256::zero(), U256::one(), U256::MAX, U256::one() << 128] {
+            let (high, low) = split_word(x);
+            assert_eq!(combine_hi_lo(high, low), x);
+        }
+    }
 }